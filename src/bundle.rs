@@ -1,30 +1,65 @@
 //! Parsing for bundle.yaml files
 
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 use ex::fs::{read, write};
+use log::info;
 use rayon::prelude::*;
+use serde::Deserialize as _;
 use serde_derive::{Deserialize, Serialize};
-use serde_yaml::{from_slice, to_vec};
+use serde_yaml::{from_slice, from_value, to_vec, Deserializer, Value as YamlValue};
 
-use crate::charm_source::CharmSource;
+use crate::channel::Channel;
+use crate::charm_source::{CharmSource, UploadReport};
 use crate::charm_url::CharmURL;
-use crate::cmd;
+use crate::cmd::{self, Runner};
 use crate::error::JujuError;
+use crate::local::model_args;
 use crate::paths;
 use crate::series::Series;
 
 /// Represents a YAML value that doesn't have a pre-determined type
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Value {
     String(String),
     Integer(i64),
+    Float(f64),
     Boolean(bool),
     None,
 }
 
+/// Floats are compared by their bits, so that every value, including NaN, equals itself
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::None, Value::None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::String(s) => f.write_str(s),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::None => Ok(()),
+        }
+    }
+}
+
 /// Arbitrary annotations for an application
 ///
 /// TODO: These seem to be the only ones in use, are there any others?
@@ -81,7 +116,7 @@ pub struct Application {
     pub devices: HashMap<String, String>,
 
     /// Maps how endpoints are bound to spaces
-    #[serde(default)]
+    #[serde(default, alias = "bindings")]
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub endpoint_bindings: HashMap<String, String>,
 
@@ -137,19 +172,114 @@ pub struct Application {
     pub storage: HashMap<String, String>,
 
     /// Which Node (Kubernetes) or Unit (IaaS) this charm should be assigned to
-    #[serde(default)]
+    ///
+    /// Machine IDs may be written as plain integers. See `Application::placements` for the
+    /// parsed form.
+    #[serde(default, deserialize_with = "strings_or_integers")]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub to: Vec<String>,
 }
 
+/// Deserializes a list whose items may be strings or integers, such as `to: [0, lxd:1]`
+fn strings_or_integers<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Vec::<YamlValue>::deserialize(deserializer)?
+        .into_iter()
+        .map(|mut value| {
+            stringify(&mut value);
+            match value {
+                YamlValue::String(s) => Ok(s),
+                other => Err(serde::de::Error::custom(format!(
+                    "Expected a placement, got {:?}",
+                    other
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// What a unit is placed on, within an optional container such as `lxd`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlacementTarget {
+    /// A machine declared in the bundle's `machines`
+    Machine(String),
+
+    /// Alongside an application's units, or a particular unit of it
+    Application { name: String, unit: Option<u32> },
+
+    /// A new machine
+    New,
+}
+
+/// A parsed entry of an application's `to`, e.g. `lxd:0` or `mysql/1`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    /// Type of container to put the unit in, e.g. `lxd`
+    pub container: Option<String>,
+
+    pub target: PlacementTarget,
+}
+
+impl FromStr for Placement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (container, target) = match s.split_once(':') {
+            Some((container, target)) => (Some(container.to_string()), target),
+            None => (None, s),
+        };
+
+        let target = if target == "new" {
+            PlacementTarget::New
+        } else if !target.is_empty() && target.chars().all(|ch| ch.is_ascii_digit()) {
+            PlacementTarget::Machine(target.into())
+        } else {
+            let (name, unit) = match target.split_once('/') {
+                Some((name, unit)) => (
+                    name,
+                    Some(
+                        unit.parse()
+                            .map_err(|_| format!("Invalid unit in placement `{}`", s))?,
+                    ),
+                ),
+                None => (target, None),
+            };
+            if name.is_empty() {
+                return Err(format!("Invalid placement `{}`", s));
+            }
+            PlacementTarget::Application {
+                name: name.into(),
+                unit,
+            }
+        };
+
+        Ok(Placement { container, target })
+    }
+}
+
 impl Application {
-    pub fn upgrade(&self, name: &str) -> Result<(), JujuError> {
+    /// Parses where each unit should be placed, from `to`
+    pub fn placements(&self) -> Result<Vec<Placement>, String> {
+        self.to.iter().map(|to| to.parse()).collect()
+    }
+
+    /// Upgrades the deployed application to the locally built charm
+    ///
+    /// Targets the given controller and model, or the current ones if unset.
+    pub fn upgrade(
+        &self,
+        name: &str,
+        controller: Option<&str>,
+        model: Option<&str>,
+    ) -> Result<(), JujuError> {
         let source_dir = self
             .charm
             .as_ref()
             .map(ToString::to_string)
             .expect("Built charm directory can't be empty");
-        let charm = CharmSource::load(&PathBuf::from(&source_dir))?;
+        let charm = CharmSource::load(PathBuf::from(&source_dir))?;
         let resources = charm.resources_with_defaults(&self.resources)?;
 
         let args = vec!["upgrade-charm", name, "--path", &source_dir]
@@ -160,11 +290,59 @@ impl Application {
                     .iter()
                     .map(|(k, v)| format!("--resource={}={}", k, v)),
             )
+            .chain(model_args(controller, model)?)
             .collect::<Vec<_>>();
 
         cmd::run("juju", &args)
     }
 
+    /// Deploys the application's charm as `name`
+    ///
+    /// Passes `--trust` if `trust` is set or the bundle marks the application as trusted.
+    /// Targets the given controller and model, or the current ones if unset.
+    pub fn deploy(
+        &self,
+        name: &str,
+        controller: Option<&str>,
+        model: Option<&str>,
+        trust: bool,
+    ) -> Result<(), JujuError> {
+        let args: Vec<_> = self
+            .deploy_args(name, trust)?
+            .into_iter()
+            .chain(model_args(controller, model)?)
+            .collect();
+
+        cmd::run("juju", &args)
+    }
+
+    fn deploy_args(&self, name: &str, trust: bool) -> Result<Vec<String>, JujuError> {
+        let charm = match &self.charm {
+            Some(charm) => charm.to_string(),
+            None => return Err(JujuError::MissingSourceError(name.into())),
+        };
+
+        let mut args = vec!["deploy".to_string(), charm, name.to_string()];
+
+        if self.scale > 0 {
+            args.push(format!("--num-units={}", self.scale));
+        }
+
+        let mut resources: Vec<_> = self.resources.iter().collect();
+        resources.sort_unstable();
+        args.extend(
+            resources
+                .into_iter()
+                .map(|(k, v)| format!("--resource={}={}", k, v)),
+        );
+
+        if trust || self.trust {
+            args.push("--trust".into());
+        }
+
+        Ok(args)
+    }
+
     /// Calculates the path to the charm's source directory
     ///
     /// This can be either manually set with `source: ./foo` in `bundle.yaml`,
@@ -199,7 +377,7 @@ impl Application {
         bundle_path: &str,
         channels: &[String],
         destructive_mode: bool,
-    ) -> Result<String, JujuError> {
+    ) -> Result<UploadReport, JujuError> {
         let name = self
             .charm
             .as_ref()
@@ -220,8 +398,7 @@ impl Application {
 
                 let charm = CharmSource::load(&charm_path)?;
 
-                let rev_url = charm.upload_charmhub(&self.resources, channels, destructive_mode)?;
-                Ok(rev_url)
+                charm.upload_charmhub(&self.resources, channels, destructive_mode)
             }
             None => {
                 unreachable!()
@@ -230,7 +407,79 @@ impl Application {
     }
 }
 
+/// A machine declared in a bundle, for placing units on
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct Machine {
+    /// Constraints such as `cores=2 mem=4G`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constraints: Option<String>,
+
+    /// OS series to use for the machine
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub series: Option<String>,
+
+    /// OS base to use for the machine, e.g. `ubuntu@22.04`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+
+    /// Arbitrary annotations intepreted by things other than Juju itself
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub annotations: HashMap<String, String>,
+}
+
+/// Summary of the differences between two bundles
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BundleDiff {
+    /// Applications only in the other bundle
+    pub added_applications: Vec<String>,
+
+    /// Applications only in this bundle
+    pub removed_applications: Vec<String>,
+
+    /// Applications in both bundles, but defined differently
+    pub changed_applications: Vec<String>,
+
+    /// Relations only in the other bundle
+    pub added_relations: Vec<Vec<String>>,
+
+    /// Relations only in this bundle
+    pub removed_relations: Vec<Vec<String>>,
+}
+
+impl BundleDiff {
+    /// Whether the bundles are equivalent
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Quotes `arg` for a POSIX shell, unless it only has characters that are safe unquoted
+fn shell_quote(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c);
+
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        arg.into()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Converts a YAML scalar such as an integer into a string, leaving other values alone
+fn stringify(value: &mut YamlValue) {
+    let string = match value {
+        YamlValue::Number(n) => n.to_string(),
+        YamlValue::Bool(b) => b.to_string(),
+        _ => return,
+    };
+    *value = YamlValue::String(string);
+}
+
 /// Represents a `bundle.yaml` file
+///
+/// Comparing and inspecting bundles, e.g. with `diff`, `resource_diff`, and `plan`, never runs
+/// external commands.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Bundle {
     /// Bundle name, used for uploading to charm store
@@ -250,6 +499,11 @@ pub struct Bundle {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
+    /// Machines that units may be placed on, keyed by machine ID
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub machines: HashMap<String, Machine>,
+
     /// Pairs of application names that require a relation between them
     #[serde(default)]
     pub relations: Vec<Vec<String>>,
@@ -267,6 +521,152 @@ impl Bundle {
         Ok(from_slice(&read(path.into())?)?)
     }
 
+    /// Parses the output of `juju export-bundle`
+    ///
+    /// Exported bundles differ from hand-written ones in a few ways: resource revisions and
+    /// machine IDs are integers, and any offers are appended as an overlay document, which is
+    /// ignored here. Options keep their types, including the floats that
+    /// `--include-charm-defaults` emits.
+    pub fn from_exported(yaml: &str) -> Result<Self, JujuError> {
+        let mut doc = match Deserializer::from_str(yaml).next() {
+            Some(doc) => YamlValue::deserialize(doc)?,
+            None => YamlValue::Null,
+        };
+
+        if let Some(machines) = doc.get_mut("machines").and_then(YamlValue::as_mapping_mut) {
+            *machines = machines
+                .iter()
+                .map(|(k, v)| {
+                    let mut k = k.clone();
+                    stringify(&mut k);
+                    (k, v.clone())
+                })
+                .collect();
+        }
+
+        let apps = doc
+            .get_mut("applications")
+            .and_then(YamlValue::as_mapping_mut)
+            .into_iter()
+            .flat_map(|apps| apps.iter_mut().map(|(_, app)| app));
+
+        for app in apps {
+            if let Some(resources) = app.get_mut("resources").and_then(YamlValue::as_mapping_mut) {
+                resources.iter_mut().for_each(|(_, v)| stringify(v));
+            }
+
+            if let Some(to) = app.get_mut("to").and_then(YamlValue::as_sequence_mut) {
+                to.iter_mut().for_each(stringify);
+            }
+        }
+
+        Ok(from_value(doc)?)
+    }
+
+    /// The machines declared by the bundle, sorted by ID
+    ///
+    /// Numeric IDs are sorted numerically, so that e.g. `10` comes after `9`.
+    pub fn machines(&self) -> Vec<(&str, &Machine)> {
+        let mut machines: Vec<_> = self
+            .machines
+            .iter()
+            .map(|(id, machine)| (id.as_str(), machine))
+            .collect();
+
+        machines.sort_by_key(|(id, _)| (id.parse::<u64>().unwrap_or(u64::MAX), *id));
+        machines
+    }
+
+    /// Summarizes how `other` differs from this bundle
+    ///
+    /// Relations are compared regardless of the order of their endpoints.
+    pub fn diff(&self, other: &Bundle) -> BundleDiff {
+        let mut diff = BundleDiff::default();
+
+        for (name, app) in &self.applications {
+            match other.applications.get(name) {
+                None => diff.removed_applications.push(name.clone()),
+                Some(other_app) if other_app != app => diff.changed_applications.push(name.clone()),
+                Some(_) => {}
+            }
+        }
+
+        diff.added_applications = other
+            .applications
+            .keys()
+            .filter(|name| !self.applications.contains_key(*name))
+            .cloned()
+            .collect();
+
+        let normalize = |relations: &[Vec<String>]| -> HashSet<Vec<String>> {
+            relations
+                .iter()
+                .map(|rel| {
+                    let mut rel = rel.clone();
+                    rel.sort();
+                    rel
+                })
+                .collect()
+        };
+        let ours = normalize(&self.relations);
+        let theirs = normalize(&other.relations);
+
+        diff.added_relations = theirs.difference(&ours).cloned().collect();
+        diff.removed_relations = ours.difference(&theirs).cloned().collect();
+
+        diff.added_applications.sort();
+        diff.removed_applications.sort();
+        diff.changed_applications.sort();
+        diff.added_relations.sort();
+        diff.removed_relations.sort();
+
+        diff
+    }
+
+    /// Resources whose values differ between this bundle and `other`
+    ///
+    /// Keyed by `app/resource`, with the value in this bundle and in `other`. Resources that are
+    /// only set in one of the bundles have `None` for the other.
+    pub fn resource_diff(
+        &self,
+        other: &Bundle,
+    ) -> HashMap<String, (Option<String>, Option<String>)> {
+        let resources = |bundle: &Bundle| -> HashMap<String, String> {
+            bundle
+                .applications
+                .iter()
+                .flat_map(|(app, application)| {
+                    application
+                        .resources
+                        .iter()
+                        .map(move |(name, value)| (format!("{}/{}", app, name), value.clone()))
+                })
+                .collect()
+        };
+        let ours = resources(self);
+        let mut theirs = resources(other);
+
+        let mut diff: HashMap<_, _> = ours
+            .into_iter()
+            .filter_map(|(key, old)| {
+                let new = theirs.remove(&key);
+                if new.as_ref() == Some(&old) {
+                    None
+                } else {
+                    Some((key, (Some(old), new)))
+                }
+            })
+            .collect();
+
+        diff.extend(
+            theirs
+                .into_iter()
+                .map(|(key, new)| (key, (None, Some(new)))),
+        );
+
+        diff
+    }
+
     /// Save this bundle to the given path
     pub fn save<P: Into<PathBuf>>(&self, path: P) -> Result<(), JujuError> {
         write(path.into(), to_vec(self)?)?;
@@ -311,16 +711,157 @@ impl Bundle {
 
         let output = cmd::get_output("charmcraft", &args)?;
 
-        println!("\n// https://github.com/canonical/charmcraft/issues/478");
-        println!("Output from charmcraft upload in case something broke:");
-        println!("{}", String::from_utf8_lossy(&output));
+        // https://github.com/canonical/charmcraft/issues/478
+        info!(
+            "Output from charmcraft upload in case something broke:\n{}",
+            String::from_utf8_lossy(&output)
+        );
 
         Ok(())
     }
 
-    pub fn upgrade_charms(&self) -> Result<(), JujuError> {
+    /// Promotes the charm of each application in `sources` from one channel to another
+    ///
+    /// Applications are keyed by name. A failure to promote one application doesn't stop the
+    /// others from being promoted; the result for each is returned instead. Applications that
+    /// have no entry in `sources` get a `MissingSourceError`. See `CharmSource::promote` for
+    /// `allow_downgrade`, and `promoted` for the bundle to deploy from `to` afterwards.
+    pub fn promote(
+        &self,
+        from: &Channel,
+        to: &Channel,
+        sources: &HashMap<String, CharmSource>,
+        allow_downgrade: bool,
+    ) -> HashMap<String, Result<u32, JujuError>> {
+        self.applications
+            .keys()
+            .map(|name| {
+                let result = match sources.get(name) {
+                    Some(charm) => charm.promote(&charm.metadata.name, from, to, allow_downgrade),
+                    None => Err(JujuError::MissingSourceError(name.clone())),
+                };
+                (name.clone(), result)
+            })
+            .collect()
+    }
+
+    /// A copy of the bundle with every application deployed from `to`
+    ///
+    /// Config, options, and constraints are carried forward as they are, so that the promoted
+    /// bundle deploys the same way as before.
+    pub fn promoted(&self, to: &Channel) -> Bundle {
+        let mut promoted = self.clone();
+        for app in promoted.applications.values_mut() {
+            app.channel = Some(to.to_string());
+        }
+        promoted
+    }
+
+    /// The `juju` commands that deploying the bundle would run, without running them
+    ///
+    /// Applications are deployed in name order, followed by their config, whether they're
+    /// exposed, and finally the relations. Applications in `sources` are deployed from their
+    /// built artifacts, with default resources filled in. Arguments are quoted for a POSIX shell
+    /// where needed, and null options are left out, as they keep their defaults.
+    pub fn plan(&self, sources: &HashMap<String, CharmSource>) -> Result<Vec<String>, JujuError> {
+        let mut names: Vec<_> = self.applications.keys().collect();
+        names.sort_unstable();
+
+        let mut deploys = vec![];
+        let mut configs = vec![];
+        let mut exposes = vec![];
+
+        for name in names {
+            let mut app = self.applications[name].clone();
+
+            match (sources.get(name), &app.charm) {
+                (Some(charm), _) => {
+                    app.resources = charm.resources_with_defaults(&app.resources)?;
+                    app.charm = Some(charm.artifact_path()?);
+                }
+                (None, Some(_)) => {}
+                (None, None) => return Err(JujuError::MissingSourceError(name.clone())),
+            }
+            deploys.push(app.deploy_args(name, false)?);
+
+            let mut options: Vec<_> = app.config.iter().chain(&app.options).collect();
+            options.sort_unstable_by_key(|(k, _)| *k);
+            options.dedup_by_key(|(k, _)| *k);
+            options.retain(|(_, v)| **v != Value::None);
+            if !options.is_empty() {
+                configs.push(
+                    vec!["config".to_string(), name.clone()]
+                        .into_iter()
+                        .chain(options.iter().map(|(k, v)| format!("{}={}", k, v)))
+                        .collect(),
+                );
+            }
+
+            if app.expose {
+                exposes.push(vec!["expose".to_string(), name.clone()]);
+            }
+        }
+
+        let relations = self.relations.iter().map(|relation| {
+            std::iter::once("relate".to_string())
+                .chain(relation.iter().cloned())
+                .collect::<Vec<_>>()
+        });
+
+        Ok(deploys
+            .into_iter()
+            .chain(configs)
+            .chain(exposes)
+            .chain(relations)
+            .map(|args| {
+                let args: Vec<_> = args.iter().map(|arg| shell_quote(arg)).collect();
+                format!("juju {}", args.join(" "))
+            })
+            .collect())
+    }
+
+    /// Builds the charm of each application whose `charm` is a local source directory
+    ///
+    /// Relative paths are resolved from the current directory. Store charms and already built
+    /// `.charm` files are skipped. Returns the artifact paths of each built charm, keyed by
+    /// application name.
+    pub fn build_all(
+        &self,
+        destructive_mode: bool,
+    ) -> Result<HashMap<String, Vec<PathBuf>>, JujuError> {
+        self.build_all_with(destructive_mode, &Runner::default())
+    }
+
+    fn build_all_with(
+        &self,
+        destructive_mode: bool,
+        runner: &Runner,
+    ) -> Result<HashMap<String, Vec<PathBuf>>, JujuError> {
+        self.applications
+            .iter()
+            .filter_map(|(name, app)| {
+                let path = app.charm.as_ref()?.as_local_path()?;
+                Some((name, path)).filter(|(_, path)| path.is_dir())
+            })
+            .map(|(name, path)| {
+                info!("Building {}", name);
+
+                let mut charm = CharmSource::load(path)?;
+                charm.runner = runner.clone();
+                charm.build(destructive_mode)?;
+
+                Ok((name.clone(), charm.artifact_paths()))
+            })
+            .collect()
+    }
+
+    pub fn upgrade_charms(
+        &self,
+        controller: Option<&str>,
+        model: Option<&str>,
+    ) -> Result<(), JujuError> {
         for (name, app) in &self.applications {
-            app.upgrade(name)?;
+            app.upgrade(name, controller, model)?;
         }
 
         Ok(())
@@ -352,7 +893,7 @@ impl Bundle {
                 // If the charm source was defined and either the `--build` flag was passed, or
                 // if there's no `charm` property, build the charm
                 (_, Some(source)) => {
-                    println!("Building {}", name);
+                    info!("Building {}", name);
 
                     // If `source` starts with `.`, it's a relative path from the bundle we're
                     // deploying. Otherwise, look in `CHARM_SOURCE_DIR` for it.
@@ -369,7 +910,7 @@ impl Bundle {
                     new_application.resources =
                         charm.resources_with_defaults(&new_application.resources)?;
 
-                    Some(charm.artifact_path())
+                    Some(CharmURL::from_path(charm.find_artifact()?))
                 }
 
                 // If a charm URL was defined and charm source isn't available
@@ -403,3 +944,318 @@ enum CharmStoreResponse {
         info: serde_yaml::Value,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use ex::fs::write;
+    use serde_yaml::from_str;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::charmhub;
+    use crate::cmd::mock::MockRunner;
+    use crate::testing::make_charm_zip;
+
+    fn charm(dir: &TempDir, name: &str, runner: Runner) -> CharmSource {
+        let metadata = from_str(&format!(
+            "name: {}\nsummary: {}\ndescription: {}\n",
+            name, name, name
+        ))
+        .unwrap();
+        let path = dir.path().join(format!("{}.charm", name));
+        write(&path, make_charm_zip(&metadata, None)).unwrap();
+
+        let mut charm = CharmSource::load(path).unwrap();
+        charm.runner = runner;
+        charm
+    }
+
+    #[test]
+    fn test_promote_partial_failure() {
+        let bundle: Bundle =
+            from_str("bundle: kubernetes\napplications:\n  foo: {}\n  bar: {}\n").unwrap();
+
+        let runner = Runner::new(MockRunner::new(|_, args| match args[0].as_str() {
+            "status" => Ok(charmhub::tests::STATUS.as_bytes().to_vec()),
+            "release" if args[1] == "bar" => Err(JujuError::SubcommandError(
+                "charmcraft release".into(),
+                "permission denied".into(),
+            )),
+            _ => Ok(vec![]),
+        }));
+
+        let dir = TempDir::new().unwrap();
+        let sources = [
+            ("foo".to_string(), charm(&dir, "foo", runner.clone())),
+            ("bar".to_string(), charm(&dir, "bar", runner)),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        let results = bundle.promote(&Channel::Beta, &Channel::Stable, &sources, false);
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(*results["foo"].as_ref().unwrap(), 4);
+        assert!(results["bar"].is_err());
+
+        let mut without_source = sources.clone();
+        without_source.remove("bar");
+        let results = bundle.promote(&Channel::Beta, &Channel::Stable, &without_source, false);
+        assert!(matches!(
+            &results["bar"],
+            Err(JujuError::MissingSourceError(name)) if name == "bar"
+        ));
+    }
+
+    #[test]
+    fn test_promoted() {
+        let bundle: Bundle = from_str(
+            "bundle: kubernetes\napplications:\n  foo: {charm: foo, channel: beta, constraints: mem=4G, options: {port: 80}}\n",
+        )
+        .unwrap();
+
+        let promoted = bundle.promoted(&Channel::Stable);
+        let foo = &promoted.applications["foo"];
+
+        assert_eq!(foo.channel.as_deref(), Some("stable"));
+        assert_eq!(foo.constraints.as_deref(), Some("mem=4G"));
+        assert_eq!(foo.options, bundle.applications["foo"].options);
+    }
+
+    #[test]
+    fn test_diff() {
+        let old: Bundle = from_str(
+            r#"
+bundle: kubernetes
+applications:
+  foo: {charm: foo, scale: 1}
+  bar: {charm: bar, scale: 1}
+  baz: {charm: baz, scale: 1}
+relations:
+  - [foo, bar]
+  - [baz, foo]
+"#,
+        )
+        .unwrap();
+        let new: Bundle = from_str(
+            r#"
+bundle: kubernetes
+applications:
+  foo: {charm: foo, scale: 1}
+  bar: {charm: bar, scale: 3}
+  qux: {charm: qux, scale: 1}
+relations:
+  - [bar, foo]
+  - [qux, foo]
+"#,
+        )
+        .unwrap();
+
+        let diff = old.diff(&new);
+
+        assert_eq!(diff.added_applications, ["qux"]);
+        assert_eq!(diff.removed_applications, ["baz"]);
+        assert_eq!(diff.changed_applications, ["bar"]);
+        assert_eq!(diff.added_relations, [vec!["foo", "qux"]]);
+        assert_eq!(diff.removed_relations, [vec!["baz", "foo"]]);
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn test_deploy_args_trust() {
+        let app = Application {
+            charm: Some(CharmURL::parse("foo").unwrap()),
+            scale: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            app.deploy_args("foo", true).unwrap(),
+            ["deploy", "foo", "foo", "--num-units=2", "--trust"]
+        );
+        assert!(!app
+            .deploy_args("foo", false)
+            .unwrap()
+            .contains(&"--trust".into()));
+
+        let trusted = Application { trust: true, ..app };
+        assert!(trusted
+            .deploy_args("foo", false)
+            .unwrap()
+            .contains(&"--trust".into()));
+
+        let source_only = Application {
+            charm: None,
+            source: Some("./foo".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            source_only.deploy_args("foo", false),
+            Err(JujuError::MissingSourceError(name)) if name == "foo"
+        ));
+    }
+
+    #[test]
+    fn test_resource_diff() {
+        let old: Bundle = from_str(
+            r#"
+bundle: kubernetes
+applications:
+  foo: {charm: foo, scale: 1, resources: {foo-image: "foo/foo:1.0", config: "3"}}
+  bar: {charm: bar, scale: 1, resources: {bar-image: "bar/bar:2.0"}}
+"#,
+        )
+        .unwrap();
+        let new: Bundle = from_str(
+            r#"
+bundle: kubernetes
+applications:
+  foo: {charm: foo, scale: 1, resources: {foo-image: "foo/foo:1.1", config: "3"}}
+  bar: {charm: bar, scale: 1}
+  baz: {charm: baz, scale: 1, resources: {baz-image: "baz/baz:1"}}
+"#,
+        )
+        .unwrap();
+
+        let diff = old.resource_diff(&new);
+
+        assert_eq!(diff.len(), 3);
+        assert_eq!(
+            diff["foo/foo-image"],
+            (Some("foo/foo:1.0".into()), Some("foo/foo:1.1".into()))
+        );
+        assert_eq!(diff["bar/bar-image"], (Some("bar/bar:2.0".into()), None));
+        assert_eq!(diff["baz/baz-image"], (None, Some("baz/baz:1".into())));
+        assert!(old.resource_diff(&old).is_empty());
+    }
+
+    #[test]
+    fn test_build_all() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("foo");
+        std::fs::create_dir(&source).unwrap();
+        write(
+            source.join("metadata.yaml"),
+            "name: foo\nsummary: foo\ndescription: foo\n",
+        )
+        .unwrap();
+        write(
+            source.join("charmcraft.yaml"),
+            "type: charm\nbases:\n  - build-on: [{name: ubuntu, channel: '20.04'}]\n    run-on: [{name: ubuntu, channel: '20.04'}]\n",
+        )
+        .unwrap();
+
+        let bundle: Bundle = from_str(&format!(
+            "bundle: kubernetes\napplications:\n  foo: {{charm: {}}}\n  bar: {{charm: ch:bar}}\n",
+            source.display()
+        ))
+        .unwrap();
+
+        let runner = MockRunner::new(|_, _| Ok(vec![]));
+        let built = bundle
+            .build_all_with(true, &Runner::new(runner.clone()))
+            .unwrap();
+
+        assert_eq!(built.len(), 1);
+        assert!(built["foo"][0].ends_with("foo_ubuntu-20.04-amd64.charm"));
+
+        let calls = runner.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0][..2], ["charmcraft", "pack"]);
+        assert!(calls[0].contains(&"--destructive-mode".to_string()));
+    }
+
+    #[test]
+    fn test_plan() {
+        let bundle: Bundle = from_str(
+            r#"
+bundle: kubernetes
+applications:
+  foo: {source: ./foo, scale: 2, options: {port: 8080, debug: true, motd: "it's a foo", banner: null}}
+  bar: {charm: ch:bar, scale: 1, expose: true}
+relations:
+  - [foo, bar]
+"#,
+        )
+        .unwrap();
+
+        let dir = TempDir::new().unwrap();
+        let runner = Runner::new(MockRunner::new(|_, _| Ok(vec![])));
+        let foo = charm(&dir, "foo", runner);
+        let artifact = foo.artifact_path().unwrap().to_string();
+        let sources = [("foo".to_string(), foo)].iter().cloned().collect();
+
+        assert_eq!(
+            bundle.plan(&sources).unwrap(),
+            [
+                "juju deploy ch:bar bar --num-units=1".to_string(),
+                format!("juju deploy {} foo --num-units=2", artifact),
+                r#"juju config foo debug=true 'motd=it'\''s a foo' port=8080"#.into(),
+                "juju expose bar".into(),
+                "juju relate foo bar".into(),
+            ]
+        );
+
+        assert!(matches!(
+            bundle.plan(&HashMap::new()),
+            Err(JujuError::MissingSourceError(name)) if name == "foo"
+        ));
+    }
+
+    #[test]
+    fn test_placements() {
+        let bundle: Bundle = from_str(
+            r#"
+series: focal
+machines:
+  "10": {}
+  "0": {constraints: mem=4G}
+  "1": {}
+applications:
+  mysql: {charm: mysql, scale: 2, to: [0, "lxd:1"]}
+  wordpress: {charm: wordpress, scale: 3, to: ["lxd:new", mysql/1, "10"]}
+"#,
+        )
+        .unwrap();
+
+        let ids: Vec<_> = bundle.machines().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, ["0", "1", "10"]);
+
+        assert_eq!(
+            bundle.applications["mysql"].placements().unwrap(),
+            [
+                Placement {
+                    container: None,
+                    target: PlacementTarget::Machine("0".into()),
+                },
+                Placement {
+                    container: Some("lxd".into()),
+                    target: PlacementTarget::Machine("1".into()),
+                },
+            ]
+        );
+        assert_eq!(
+            bundle.applications["wordpress"].placements().unwrap(),
+            [
+                Placement {
+                    container: Some("lxd".into()),
+                    target: PlacementTarget::New,
+                },
+                Placement {
+                    container: None,
+                    target: PlacementTarget::Application {
+                        name: "mysql".into(),
+                        unit: Some(1),
+                    },
+                },
+                Placement {
+                    container: None,
+                    target: PlacementTarget::Machine("10".into()),
+                },
+            ]
+        );
+
+        assert!("mysql/x".parse::<Placement>().is_err());
+    }
+}
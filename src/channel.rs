@@ -1,9 +1,10 @@
+use std::fmt;
 use std::str::FromStr;
 
 use serde_derive::{Deserialize, Serialize};
 use serde_yaml::{from_slice, Error};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub enum Channel {
     Unpublished,
@@ -13,16 +14,22 @@ pub enum Channel {
     Stable,
 }
 
-impl ToString for Channel {
-    fn to_string(&self) -> String {
+impl Channel {
+    /// The next more stable channel, which a tracking channel follows
+    pub fn more_stable(&self) -> Option<Channel> {
         match self {
-            Channel::Unpublished => "unpublished",
-            Channel::Edge => "edge",
-            Channel::Beta => "beta",
-            Channel::Candidate => "candidate",
-            Channel::Stable => "stable",
+            Channel::Unpublished => Some(Channel::Edge),
+            Channel::Edge => Some(Channel::Beta),
+            Channel::Beta => Some(Channel::Candidate),
+            Channel::Candidate => Some(Channel::Stable),
+            Channel::Stable => None,
         }
-        .into()
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str((*self).into())
     }
 }
 
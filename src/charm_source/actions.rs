@@ -0,0 +1,97 @@
+use indexmap::IndexMap;
+use serde_derive::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+/// An action that can be run on the charm's units
+///
+/// Parameters are described with JSON Schema. actions.yaml lists them under `params`, which
+/// Juju turns into the schema's `properties`, so either name is accepted.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct Action {
+    /// What the action does
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// JSON Schema of each parameter, by name
+    #[serde(default, alias = "properties")]
+    pub params: IndexMap<String, Value>,
+
+    /// Names of the parameters that must be given
+    #[serde(default)]
+    pub required: Vec<String>,
+
+    /// Whether parameters not in `params` are accepted
+    #[serde(default, rename = "additionalProperties")]
+    pub additional_properties: Option<bool>,
+
+    /// Whether the action may run while other actions are running
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallel: Option<bool>,
+
+    /// Actions in the same group are run one at a time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_group: Option<String>,
+}
+
+/// A charm's actions.yaml file, i.e. its actions by name
+pub type Actions = IndexMap<String, Action>;
+
+impl Action {
+    /// Names of the action's parameters, in declaration order
+    pub fn param_names(&self) -> Vec<&str> {
+        self.params.keys().map(String::as_str).collect()
+    }
+
+    /// Names of the parameters that must be given, in declaration order
+    pub fn required_params(&self) -> Vec<&str> {
+        self.params
+            .keys()
+            .filter(|name| self.required.contains(name))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::from_str;
+
+    use super::*;
+
+    #[test]
+    fn test_params() {
+        let actions: Actions = from_str(
+            r#"
+backup:
+  description: Backs up the database
+  params:
+    target:
+      type: string
+      description: Where to put the backup
+    compress:
+      type: boolean
+      default: true
+    retries:
+      type: integer
+  required: [target, retries]
+  additionalProperties: false
+restart:
+  description: Restarts the service
+"#,
+        )
+        .unwrap();
+
+        let backup = &actions["backup"];
+        assert_eq!(backup.param_names(), ["target", "compress", "retries"]);
+        assert_eq!(backup.required_params(), ["target", "retries"]);
+        assert_eq!(backup.additional_properties, Some(false));
+
+        assert!(actions["restart"].param_names().is_empty());
+        assert!(actions["restart"].required_params().is_empty());
+
+        let schema: Action =
+            from_str("properties:\n  target: {type: string}\nrequired: [target]\n").unwrap();
+        assert_eq!(schema.required_params(), ["target"]);
+    }
+}
@@ -15,10 +15,34 @@ pub struct BaseSpec {
 }
 
 /// A charm's charmcraft.yaml file
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "kebab-case", tag = "type")]
 pub struct Charmcraft {
     pub bases: Vec<BaseSpec>,
     #[serde(default)]
     pub architectures: Vec<String>,
 }
+
+/// How much `charmcraft` logs, as set with `--verbosity`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verbosity {
+    Quiet,
+    Brief,
+    Verbose,
+    Debug,
+    Trace,
+}
+
+impl Verbosity {
+    /// The value of the `--verbosity` flag for this level
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Verbosity::Quiet => "quiet",
+            Verbosity::Brief => "brief",
+            Verbosity::Verbose => "verbose",
+            Verbosity::Debug => "debug",
+            Verbosity::Trace => "trace",
+        }
+    }
+}
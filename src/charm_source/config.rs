@@ -1,30 +1,691 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 
+use ex::fs::read;
+use indexmap::IndexMap;
 use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use serde_yaml::{from_slice, Value};
+
+use crate::error::JujuError;
 
 /// Config option as defined in config.yaml
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 pub enum ConfigOption {
     /// String config option
-    #[serde(rename_all = "kebab-case")]
+    ///
+    /// Juju also accepts `type: str`, which is written back out as `string`.
+    #[serde(alias = "str", rename_all = "kebab-case")]
     String {
         default: Option<String>,
         description: String,
+
+        /// Values that the option is restricted to
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        choices: Option<Vec<String>>,
     },
 
     /// Integer config option
     #[serde(rename = "int", rename_all = "kebab-case")]
-    Integer { default: i64, description: String },
+    Integer {
+        default: i64,
+        description: String,
+
+        /// Values that the option is restricted to
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        choices: Option<Vec<i64>>,
+    },
 
     /// Boolean config option
-    #[serde(rename_all = "kebab-case")]
+    ///
+    /// Juju also accepts `type: bool`, which is written back out as `boolean`.
+    #[serde(alias = "bool", rename_all = "kebab-case")]
     Boolean { default: bool, description: String },
+
+    /// Floating point config option
+    #[serde(rename_all = "kebab-case")]
+    Float {
+        default: f64,
+        description: String,
+
+        /// Values that the option is restricted to
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        choices: Option<Vec<f64>>,
+    },
+
+    /// Juju secret config option, whose value is a secret URI
+    #[serde(rename_all = "kebab-case")]
+    Secret {
+        default: Option<String>,
+        description: String,
+    },
+}
+
+impl ConfigOption {
+    /// The option's type, as written in the `type` field of config.yaml
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ConfigOption::String { .. } => "string",
+            ConfigOption::Integer { .. } => "int",
+            ConfigOption::Boolean { .. } => "boolean",
+            ConfigOption::Float { .. } => "float",
+            ConfigOption::Secret { .. } => "secret",
+        }
+    }
+
+    /// The option's default, if it has one
+    pub fn default_value(&self) -> Option<Value> {
+        match self {
+            ConfigOption::String { default, .. } | ConfigOption::Secret { default, .. } => {
+                default.clone().map(Value::String)
+            }
+            ConfigOption::Integer { default, .. } => Some(Value::Number((*default).into())),
+            ConfigOption::Boolean { default, .. } => Some(Value::Bool(*default)),
+            ConfigOption::Float { default, .. } => Some(Value::Number((*default).into())),
+        }
+    }
+
+    /// The values that the option is restricted to, if any
+    pub fn choices(&self) -> Option<Vec<Value>> {
+        match self {
+            ConfigOption::String { choices, .. } => choices
+                .as_ref()
+                .map(|c| c.iter().cloned().map(Value::String).collect()),
+            ConfigOption::Integer { choices, .. } => choices
+                .as_ref()
+                .map(|c| c.iter().map(|&i| Value::Number(i.into())).collect()),
+            ConfigOption::Float { choices, .. } => choices
+                .as_ref()
+                .map(|c| c.iter().map(|&f| Value::Number(f.into())).collect()),
+            ConfigOption::Boolean { .. } | ConfigOption::Secret { .. } => None,
+        }
+    }
+
+    /// The JSON Schema type that values of the option have
+    fn json_schema_type(&self) -> &'static str {
+        match self {
+            ConfigOption::String { .. } | ConfigOption::Secret { .. } => "string",
+            ConfigOption::Integer { .. } => "integer",
+            ConfigOption::Boolean { .. } => "boolean",
+            ConfigOption::Float { .. } => "number",
+        }
+    }
+
+    /// The option's description
+    pub fn description(&self) -> &str {
+        match self {
+            ConfigOption::String { description, .. }
+            | ConfigOption::Integer { description, .. }
+            | ConfigOption::Boolean { description, .. }
+            | ConfigOption::Float { description, .. }
+            | ConfigOption::Secret { description, .. } => description,
+        }
+    }
+
+    /// Whether `value` has the right type to be set for this option
+    pub fn accepts(&self, value: &Value) -> bool {
+        match self {
+            ConfigOption::String { .. } | ConfigOption::Secret { .. } => value.is_string(),
+            ConfigOption::Integer { .. } => value.is_i64() || value.is_u64(),
+            ConfigOption::Boolean { .. } => value.is_bool(),
+            ConfigOption::Float { .. } => value.is_number(),
+        }
+    }
+
+    /// Whether `value` is one of the option's choices, or the option has none
+    pub fn is_choice(&self, value: &Value) -> bool {
+        match self {
+            ConfigOption::Float {
+                choices: Some(choices),
+                ..
+            } => value.as_f64().is_some_and(|v| choices.contains(&v)),
+            _ => self.choices().is_none_or(|c| c.contains(value)),
+        }
+    }
 }
 
 /// A charm's config.yaml file
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
-    pub options: HashMap<String, ConfigOption>,
+    /// The options, in the order that they're declared
+    pub options: IndexMap<String, ConfigOption>,
+}
+
+impl Config {
+    /// Checks that each option's default in the raw config.yaml has the option's declared type
+    ///
+    /// A mismatch such as `default: "80"` on an int option would otherwise only show up as a
+    /// generic YAML error when loading. Options of unknown types are ignored.
+    pub fn typecheck_defaults(yaml: &[u8]) -> Result<(), JujuError> {
+        let raw: Value = from_slice(yaml)?;
+        let options = match raw.get("options").and_then(Value::as_mapping) {
+            Some(options) => options,
+            None => return Ok(()),
+        };
+
+        for (name, option) in options {
+            let default = match option.get("default") {
+                Some(default) if !default.is_null() => default,
+                _ => continue,
+            };
+
+            let (expected, matches) = match option.get("type").and_then(Value::as_str) {
+                Some("string" | "str") => ("string", default.is_string()),
+                Some("secret") => ("secret", default.is_string()),
+                Some("int") => ("int", default.is_i64() || default.is_u64()),
+                Some("float") => ("float", default.is_number()),
+                Some("boolean" | "bool") => ("boolean", default.is_bool()),
+                _ => continue,
+            };
+
+            if !matches {
+                return Err(JujuError::InvalidDefault(
+                    name.as_str().unwrap_or_default().into(),
+                    format!("expected a {} value", expected),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Loads several config.yaml-style files, e.g. a committed config.yaml plus environment
+    /// overrides
+    ///
+    /// Options in later files replace those of the same name in earlier files, but may not
+    /// change the option's type.
+    pub fn load_layered(paths: &[PathBuf]) -> Result<Config, JujuError> {
+        let mut merged = Config {
+            options: IndexMap::new(),
+        };
+
+        for path in paths {
+            let layer: Config = from_slice(&read(path)?)?;
+
+            for (name, option) in layer.options {
+                if let Some(existing) = merged.options.get(&name) {
+                    if existing.type_name() != option.type_name() {
+                        return Err(JujuError::ConfigConflict(name, path.display().to_string()));
+                    }
+                }
+                merged.options.insert(name, option);
+            }
+        }
+
+        Ok(merged)
+    }
+
+    /// Names of the options, sorted lexicographically
+    pub fn option_names_sorted(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.options.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Serializes the config as config.yaml, with options sorted by name
+    ///
+    /// Multi-line descriptions are written as block scalars, so they survive a round trip.
+    pub fn to_yaml(&self) -> Result<String, JujuError> {
+        let mut options = serde_yaml::Mapping::new();
+        for name in self.option_names_sorted() {
+            options.insert(
+                Value::String(name.into()),
+                serde_yaml::to_value(&self.options[name])?,
+            );
+        }
+
+        let mut config = serde_yaml::Mapping::new();
+        config.insert("options".into(), Value::Mapping(options));
+
+        super::yaml::to_string(&Value::Mapping(config))
+    }
+
+    /// Serializes the config as JSON
+    pub fn to_json(&self) -> Result<String, JujuError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Renders the options as a Markdown table of name, type, default and description, sorted
+    /// by name, e.g. for a charm's docs page
+    pub fn to_markdown(&self) -> String {
+        let mut table =
+            String::from("| Option | Type | Default | Description |\n| --- | --- | --- | --- |\n");
+
+        for name in self.option_names_sorted() {
+            let option = &self.options[name];
+            let default = match option.default_value() {
+                Some(Value::String(s)) if s.is_empty() => "`\"\"`".into(),
+                Some(Value::String(s)) => format!("`{}`", s),
+                Some(Value::Number(n)) => format!("`{}`", n),
+                Some(Value::Bool(b)) => format!("`{}`", b),
+                _ => String::new(),
+            };
+            table.push_str(&format!(
+                "| `{}` | {} | {} | {} |\n",
+                name,
+                option.type_name(),
+                markdown_cell(&default),
+                markdown_cell(option.description()),
+            ));
+        }
+
+        table
+    }
+
+    /// Translates the options into a JSON Schema object, e.g. for generating config forms
+    ///
+    /// Each option becomes a property with its type, description, default and, if it has
+    /// choices, an `enum` of them.
+    pub fn to_json_schema(&self) -> serde_json::Value {
+        let properties: serde_json::Map<_, _> = self
+            .options
+            .iter()
+            .map(|(name, option)| {
+                let mut property = json!({
+                    "type": option.json_schema_type(),
+                    "description": option.description(),
+                });
+                if let Some(default) = option.default_value() {
+                    property["default"] = serde_json::to_value(default).unwrap_or_default();
+                }
+                if let Some(choices) = option.choices() {
+                    property["enum"] = serde_json::to_value(choices).unwrap_or_default();
+                }
+                (name.clone(), property)
+            })
+            .collect();
+
+        json!({
+            "type": "object",
+            "properties": properties,
+        })
+    }
+
+    /// Default values of each option that has one
+    ///
+    /// Values keep their native YAML types, so e.g. boolean defaults serialize as `true` rather
+    /// than `"true"`.
+    ///
+    /// Errors if an option's default isn't one of its own choices.
+    pub fn defaults(&self) -> Result<HashMap<String, Value>, JujuError> {
+        let mut defaults = HashMap::new();
+
+        for (name, option) in &self.options {
+            if let Some(default) = option.default_value() {
+                if !option.is_choice(&default) {
+                    return Err(JujuError::InvalidDefault(
+                        name.clone(),
+                        "not one of the option's choices".into(),
+                    ));
+                }
+                defaults.insert(name.clone(), default);
+            }
+        }
+
+        Ok(defaults)
+    }
+
+    /// Checks that `value` can be set for the option called `name`
+    pub fn validate_value(&self, name: &str, value: &Value) -> Result<(), JujuError> {
+        let option = self
+            .options
+            .get(name)
+            .ok_or_else(|| JujuError::UnknownConfigOption(name.into()))?;
+
+        if !option.accepts(value) {
+            Err(JujuError::InvalidConfigValue(
+                name.into(),
+                option.type_name().into(),
+            ))
+        } else if !option.is_choice(value) {
+            Err(JujuError::InvalidConfigValue(
+                name.into(),
+                "one of the option's choices".into(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Computes the config an application would end up with, given `overrides` such as the
+    /// `options` set in a bundle
+    pub fn effective(
+        &self,
+        overrides: &HashMap<String, Value>,
+    ) -> Result<HashMap<String, Value>, JujuError> {
+        let mut effective = self.defaults()?;
+
+        for (name, value) in overrides {
+            self.validate_value(name, value)?;
+            effective.insert(name.clone(), value.clone());
+        }
+
+        Ok(effective)
+    }
+}
+
+/// Makes `text` fit in a table cell, joining its lines and escaping pipes
+fn markdown_cell(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::from_str;
+
+    use super::*;
+
+    #[test]
+    fn test_type_name() {
+        let config: Config = from_str(
+            r#"
+options:
+  a: {type: string, description: A, default: foo}
+  b: {type: int, description: B, default: 1}
+  c: {type: boolean, description: C, default: true}
+  d: {type: float, description: D, default: 0.5}
+  e: {type: secret, description: E}
+"#,
+        )
+        .unwrap();
+
+        let names = [
+            ("a", "string"),
+            ("b", "int"),
+            ("c", "boolean"),
+            ("d", "float"),
+            ("e", "secret"),
+        ];
+
+        for (option, expected) in &names {
+            assert_eq!(config.options[*option].type_name(), *expected);
+        }
+    }
+
+    fn sample() -> Config {
+        from_str(
+            r#"
+options:
+  name: {type: string, description: Name, default: foo}
+  port: {type: int, description: Port, default: 80}
+  debug: {type: boolean, description: Debug, default: false}
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_declaration_order() {
+        let names: Vec<_> = sample().options.keys().cloned().collect();
+        assert_eq!(names, ["name", "port", "debug"]);
+    }
+
+    #[test]
+    fn test_option_names_sorted() {
+        assert_eq!(sample().option_names_sorted(), ["debug", "name", "port"]);
+    }
+
+    #[test]
+    fn test_effective_override() {
+        let overrides = [("port".to_string(), Value::from(8080))]
+            .iter()
+            .cloned()
+            .collect();
+
+        let effective = sample().effective(&overrides).unwrap();
+
+        assert_eq!(effective["name"], Value::from("foo"));
+        assert_eq!(effective["port"], Value::from(8080));
+        assert_eq!(effective["debug"], Value::from(false));
+    }
+
+    #[test]
+    fn test_effective_errors() {
+        let unknown = [("colour".to_string(), Value::from("blue"))]
+            .iter()
+            .cloned()
+            .collect();
+        assert!(matches!(
+            sample().effective(&unknown),
+            Err(JujuError::UnknownConfigOption(name)) if name == "colour"
+        ));
+
+        let mistyped = [("port".to_string(), Value::from("eighty"))]
+            .iter()
+            .cloned()
+            .collect();
+        assert!(matches!(
+            sample().effective(&mistyped),
+            Err(JujuError::InvalidConfigValue(name, _)) if name == "port"
+        ));
+    }
+
+    #[test]
+    fn test_choices() {
+        let config: Config = from_str(
+            r#"
+options:
+  log-level: {type: string, description: Log level, default: info, choices: [debug, info]}
+  workers: {type: int, description: Workers, default: 2, choices: [1, 2, 4]}
+"#,
+        )
+        .unwrap();
+
+        let defaults = config.defaults().unwrap();
+        assert_eq!(defaults["log-level"], Value::from("info"));
+        assert_eq!(defaults["workers"], Value::from(2));
+
+        assert!(config
+            .validate_value("log-level", &Value::from("debug"))
+            .is_ok());
+        assert!(matches!(
+            config.validate_value("workers", &Value::from(3)),
+            Err(JujuError::InvalidConfigValue(name, _)) if name == "workers"
+        ));
+    }
+
+    #[test]
+    fn test_default_not_in_choices() {
+        let config: Config = from_str(
+            r#"
+options:
+  workers: {type: int, description: Workers, default: 8, choices: [1, 2, 4]}
+"#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            config.defaults(),
+            Err(JujuError::InvalidDefault(name, _)) if name == "workers"
+        ));
+    }
+
+    #[test]
+    fn test_defaults_native_types() {
+        let defaults = sample().defaults().unwrap();
+
+        assert_eq!(
+            serde_yaml::to_string(&defaults["debug"])
+                .unwrap()
+                .trim_start_matches("---")
+                .trim(),
+            "false"
+        );
+        assert_eq!(
+            serde_yaml::to_string(&defaults["port"])
+                .unwrap()
+                .trim_start_matches("---")
+                .trim(),
+            "80"
+        );
+
+        let option = serde_yaml::to_string(&sample().options["debug"]).unwrap();
+        assert!(option.contains("default: false"), "{}", option);
+    }
+
+    #[test]
+    fn test_load_layered() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let base = dir.path().join("config.yaml");
+        let overrides = dir.path().join("staging.yaml");
+        let conflicting = dir.path().join("broken.yaml");
+
+        ex::fs::write(&base, serde_yaml::to_vec(&sample()).unwrap()).unwrap();
+        ex::fs::write(
+            &overrides,
+            "options:\n  port: {type: int, description: Port, default: 8080}\n  region: {type: string, description: Region, default: eu}\n",
+        )
+        .unwrap();
+        ex::fs::write(
+            &conflicting,
+            "options:\n  debug: {type: string, description: Debug, default: 'yes'}\n",
+        )
+        .unwrap();
+
+        let config = Config::load_layered(&[base.clone(), overrides]).unwrap();
+        let defaults = config.defaults().unwrap();
+        assert_eq!(defaults["name"], Value::from("foo"));
+        assert_eq!(defaults["port"], Value::from(8080));
+        assert_eq!(defaults["region"], Value::from("eu"));
+
+        match Config::load_layered(&[base, conflicting.clone()]) {
+            Err(JujuError::ConfigConflict(name, source)) => {
+                assert_eq!(name, "debug");
+                assert_eq!(source, conflicting.display().to_string());
+            }
+            other => panic!("Expected a conflict, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_block_scalar_round_trip() {
+        let yaml = r#"options:
+  motd:
+    type: string
+    default: hello
+    description: |
+      Message of the day.
+
+        Shown verbatim, including indentation.
+      Set to an empty string to disable.
+  port:
+    type: int
+    default: 80
+    description: Port to listen on
+"#;
+        let config: Config = from_str(yaml).unwrap();
+
+        match &config.options["motd"] {
+            ConfigOption::String { description, .. } => assert_eq!(
+                description,
+                "Message of the day.\n\n  Shown verbatim, including indentation.\nSet to an empty string to disable.\n"
+            ),
+            other => panic!("Expected a string option, got {:?}", other),
+        }
+
+        assert_eq!(config.to_yaml().unwrap(), yaml);
+    }
+
+    #[test]
+    fn test_to_json_schema() {
+        let config: Config = from_str(
+            r#"
+options:
+  name: {type: string, description: Name, default: foo, choices: [foo, bar]}
+  port: {type: int, description: Port, default: 80}
+  debug: {type: boolean, description: Debug, default: false}
+  ratio: {type: float, description: Ratio, default: 0.5, choices: [0.5, 1.0]}
+  password: {type: secret, description: Password}
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.to_json_schema(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "name": {
+                        "type": "string",
+                        "description": "Name",
+                        "default": "foo",
+                        "enum": ["foo", "bar"],
+                    },
+                    "port": {"type": "integer", "description": "Port", "default": 80},
+                    "debug": {"type": "boolean", "description": "Debug", "default": false},
+                    "ratio": {
+                        "type": "number",
+                        "description": "Ratio",
+                        "default": 0.5,
+                        "enum": [0.5, 1.0],
+                    },
+                    "password": {"type": "string", "description": "Password"},
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_typecheck_defaults() {
+        Config::typecheck_defaults(serde_yaml::to_string(&sample()).unwrap().as_bytes()).unwrap();
+
+        let mistyped = b"options:\n  port: {type: int, description: Port, default: eighty}\n";
+        assert!(matches!(
+            Config::typecheck_defaults(mistyped),
+            Err(JujuError::InvalidDefault(name, _)) if name == "port"
+        ));
+
+        let float = b"options:\n  workers: {type: int, description: Workers, default: 1.5}\n";
+        assert!(Config::typecheck_defaults(float).is_err());
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        let config: Config = from_str(
+            r#"
+options:
+  port: {type: int, description: Port to listen on, default: 80}
+  name: {type: string, description: "Name, either\nfoo | bar", default: foo}
+  password: {type: secret, description: Password}
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.to_markdown(),
+            "| Option | Type | Default | Description |\n\
+             | --- | --- | --- | --- |\n\
+             | `name` | string | `foo` | Name, either foo \\| bar |\n\
+             | `password` | secret |  | Password |\n\
+             | `port` | int | `80` | Port to listen on |\n"
+        );
+    }
+
+    #[test]
+    fn test_type_aliases() {
+        let yaml = br#"
+options:
+  name: {type: str, description: Name, default: foo}
+  debug: {type: bool, description: Debug, default: false}
+"#;
+        Config::typecheck_defaults(yaml).unwrap();
+        let config: Config = from_slice(yaml).unwrap();
+
+        assert_eq!(config.options["name"].type_name(), "string");
+        assert_eq!(config.options["debug"].type_name(), "boolean");
+
+        let written = config.to_yaml().unwrap();
+        assert!(written.contains("type: string"), "{}", written);
+        assert!(written.contains("type: boolean"), "{}", written);
+
+        assert!(matches!(
+            Config::typecheck_defaults(b"options:\n  debug: {type: bool, description: D, default: 'no'}\n"),
+            Err(JujuError::InvalidDefault(name, _)) if name == "debug"
+        ));
+    }
 }
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Deserializer};
 use serde_derive::{Deserialize, Serialize};
 
 /// A Kubernetes container for a charm
@@ -64,10 +65,199 @@ pub struct BaseContainer {
     pub mounts: Vec<ContainerMount>,
 }
 
+/// A container that sets more than `resource` or `bases` and `mounts`, e.g. the user it runs as
+///
+/// Containers that set both or neither of `resource` and `bases` are parsed as this too, so that
+/// `CharmSource::validate` can point them out.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExtendedContainer {
+    /// Reference for an entry in the resources field
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource: Option<String>,
+
+    /// A list of bases in descending order of preference for use in resolving a container image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bases: Option<Vec<ContainerBase>>,
+
+    /// List of mounted storages for this container
+    #[serde(default)]
+    pub mounts: Vec<ContainerMount>,
+
+    /// User ID to run the container's workload as
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+
+    /// Group ID to run the container's workload as
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+
+    /// User that the workload is run as, by name
+    ///
+    /// Juju 3 only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_as: Option<String>,
+}
+
 /// A Kubernetes container for a charm
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, PartialEq)]
 #[serde(rename_all = "kebab-case", untagged)]
 pub enum Container {
     Resource(ResourceContainer),
     Base(BaseContainer),
+    Extended(ExtendedContainer),
+}
+
+impl<'de> Deserialize<'de> for Container {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let container = ExtendedContainer::deserialize(deserializer)?;
+        let is_simple =
+            container.uid.is_none() && container.gid.is_none() && container.run_as.is_none();
+
+        Ok(match container {
+            ExtendedContainer {
+                resource: Some(resource),
+                bases: None,
+                mounts,
+                ..
+            } if is_simple => Container::Resource(ResourceContainer { resource, mounts }),
+            ExtendedContainer {
+                resource: None,
+                bases: Some(bases),
+                mounts,
+                ..
+            } if is_simple => Container::Base(BaseContainer { bases, mounts }),
+            extended => Container::Extended(extended),
+        })
+    }
+}
+
+impl Container {
+    /// The oci-image resource that the container is created from, if any
+    pub fn resource(&self) -> Option<&str> {
+        match self {
+            Container::Resource(c) => Some(&c.resource),
+            Container::Base(_) => None,
+            Container::Extended(c) => c.resource.as_deref(),
+        }
+    }
+
+    /// Points the container at a different oci-image resource, if it uses one
+    pub(crate) fn set_resource(&mut self, resource: String) {
+        match self {
+            Container::Resource(c) => c.resource = resource,
+            Container::Base(_) => {}
+            Container::Extended(c) => {
+                if c.resource.is_some() {
+                    c.resource = Some(resource);
+                }
+            }
+        }
+    }
+
+    /// The bases used to resolve the container's image, if any
+    pub fn bases(&self) -> Option<&[ContainerBase]> {
+        match self {
+            Container::Resource(_) => None,
+            Container::Base(c) => Some(&c.bases),
+            Container::Extended(c) => c.bases.as_deref(),
+        }
+    }
+
+    /// Storages mounted into the container
+    pub fn mounts(&self) -> &[ContainerMount] {
+        match self {
+            Container::Resource(c) => &c.mounts,
+            Container::Base(c) => &c.mounts,
+            Container::Extended(c) => &c.mounts,
+        }
+    }
+
+    fn extended(&self) -> Option<&ExtendedContainer> {
+        match self {
+            Container::Extended(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    /// User ID to run the container's workload as
+    pub fn uid(&self) -> Option<u32> {
+        self.extended().and_then(|c| c.uid)
+    }
+
+    /// Group ID to run the container's workload as
+    pub fn gid(&self) -> Option<u32> {
+        self.extended().and_then(|c| c.gid)
+    }
+
+    /// User that the workload is run as, by name
+    pub fn run_as(&self) -> Option<&str> {
+        self.extended().and_then(|c| c.run_as.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::from_str;
+
+    use super::*;
+
+    #[test]
+    fn test_uid_gid() {
+        let container: Container = from_str(
+            r#"
+bases:
+  - name: ubuntu
+    channel: "22.04"
+    architectures: [amd64]
+uid: 584792
+gid: 584793
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            container,
+            Container::Extended(ExtendedContainer {
+                bases: Some(vec![ContainerBase {
+                    name: "ubuntu".into(),
+                    channel: "22.04".into(),
+                    architectures: vec!["amd64".into()],
+                }]),
+                uid: Some(584792),
+                gid: Some(584793),
+                ..Default::default()
+            })
+        );
+        assert_eq!(container.uid(), Some(584792));
+        assert_eq!(container.bases().map(<[_]>::len), Some(1));
+    }
+
+    #[test]
+    fn test_simple_variants() {
+        let resource: Container = from_str(
+            "resource: foo-image
+",
+        )
+        .unwrap();
+        assert!(matches!(&resource, Container::Resource(c) if c.resource == "foo-image"));
+        assert_eq!(resource.uid(), None);
+
+        let base: Container = from_str(
+            "bases: []
+",
+        )
+        .unwrap();
+        assert!(matches!(base, Container::Base(_)));
+
+        let neither: Container = from_str(
+            "mounts: []
+",
+        )
+        .unwrap();
+        assert!(matches!(neither, Container::Extended(_)));
+    }
 }
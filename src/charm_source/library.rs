@@ -0,0 +1,92 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// A charm library vendored under `lib/charms/<charm>/v<N>/<name>.py`
+///
+/// See https://juju.is/docs/sdk/libraries
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct CharmLibrary {
+    /// Charm that publishes the library, with underscores as in the Python package name
+    pub charm: String,
+
+    /// Name of the library, e.g. `ingress`
+    pub name: String,
+
+    /// Unique ID of the library, from `LIBID`
+    pub id: Option<String>,
+
+    /// Major version of the library, from `LIBAPI`
+    pub api: u32,
+
+    /// Minor version of the library, from `LIBPATCH`
+    pub patch: u32,
+}
+
+impl CharmLibrary {
+    /// Parses the library at `path`, relative to the charm's root, from its `source`
+    ///
+    /// Returns `None` if the path isn't a library, or it doesn't declare `LIBAPI` and
+    /// `LIBPATCH`.
+    pub(crate) fn parse(path: &str, source: &str) -> Option<Self> {
+        let parts: Vec<_> = path.split('/').collect();
+        let (charm, name) = match parts[..] {
+            ["lib", "charms", charm, version, file]
+                if version.starts_with('v') && file.ends_with(".py") =>
+            {
+                (charm, file.trim_end_matches(".py"))
+            }
+            _ => return None,
+        };
+
+        let constant = |key: &str| {
+            source.lines().find_map(|line| {
+                let (name, value) = line.split_once('=')?;
+                Some(value.trim().trim_matches(['"', '\''])).filter(|_| name.trim() == key)
+            })
+        };
+
+        Some(Self {
+            charm: charm.into(),
+            name: name.into(),
+            id: constant("LIBID").map(String::from),
+            api: constant("LIBAPI")?.parse().ok()?,
+            patch: constant("LIBPATCH")?.parse().ok()?,
+        })
+    }
+
+    /// The version of the library, e.g. `0.3`
+    pub fn version(&self) -> String {
+        format!("{}.{}", self.api, self.patch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let library = CharmLibrary::parse(
+            "lib/charms/nginx_ingress_integrator/v0/ingress.py",
+            "\"\"\"Ingress library\"\"\"\n\nLIBID = \"db0af4367506491c91663468fb5caa4c\"\nLIBAPI = 0\nLIBPATCH = 10\n",
+        )
+        .unwrap();
+
+        assert_eq!(library.charm, "nginx_ingress_integrator");
+        assert_eq!(library.name, "ingress");
+        assert_eq!(
+            library.id.as_deref(),
+            Some("db0af4367506491c91663468fb5caa4c")
+        );
+        assert_eq!(library.version(), "0.10");
+
+        assert_eq!(
+            CharmLibrary::parse("src/charm.py", "LIBAPI = 0\nLIBPATCH = 1\n"),
+            None
+        );
+        assert_eq!(
+            CharmLibrary::parse("lib/charms/foo/v1/bar.py", "LIBAPI = 1\n"),
+            None
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// A base that the charm was built for
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ManifestBase {
+    pub name: String,
+    pub channel: String,
+
+    #[serde(default)]
+    pub architectures: Vec<String>,
+}
+
+/// The result of one of charmcraft's checks on the charm, e.g. which framework it uses
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct AnalysisAttribute {
+    pub name: String,
+    pub result: String,
+}
+
+/// What charmcraft learned about the charm while packing it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Analysis {
+    #[serde(default)]
+    pub attributes: Vec<AnalysisAttribute>,
+}
+
+/// The manifest.yaml file that charmcraft adds to a built charm
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Manifest {
+    /// Version of charmcraft that packed the charm
+    pub charmcraft_version: String,
+
+    /// When packing started, as an ISO 8601 timestamp
+    #[serde(default)]
+    pub charmcraft_started_at: Option<String>,
+
+    #[serde(default)]
+    pub bases: Vec<ManifestBase>,
+
+    #[serde(default)]
+    pub analysis: Analysis,
+}
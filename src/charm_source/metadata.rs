@@ -1,12 +1,17 @@
 use std::collections::HashMap;
 
+use indexmap::IndexMap;
 use serde_derive::{Deserialize, Serialize};
+use serde_json::to_string_pretty;
 
+use super::charmcraft::Base;
 use super::container::Container;
 use super::device::Device;
 use super::relation::Relation;
 use super::resource::Resource;
 use super::storage::Storage;
+use crate::digest::Digest;
+use crate::error::JujuError;
 
 /// A charm's metadata.yaml file
 ///
@@ -49,25 +54,26 @@ pub struct Metadata {
     ///
     /// Each key represents the name of the resource
     #[serde(default)]
-    pub resources: HashMap<String, Resource>,
+    pub resources: IndexMap<String, Resource>,
 
     /// Map of relations provided by this charm
     ///
     /// Each key represents the name of the relation as known by this charm
     #[serde(default)]
-    pub provides: HashMap<String, Relation>,
+    pub provides: IndexMap<String, Relation>,
 
     /// Map of relations required by this charm
     ///
     /// Each key represents the name of the relation as known by this charm
     #[serde(default)]
-    pub requires: HashMap<String, Relation>,
+    pub requires: IndexMap<String, Relation>,
 
     /// Mutual relations between units/peers of this charm
     ///
-    /// Each key represents the name of the relation as known by this charm
-    #[serde(default)]
-    pub peer: HashMap<String, Relation>,
+    /// Each key represents the name of the relation as known by this charm. Read from `peers`,
+    /// which is what Juju uses, with `peer` accepted as well.
+    #[serde(default, rename = "peers", alias = "peer")]
+    pub peer: IndexMap<String, Relation>,
 
     /// Storage requests for the charm
     ///
@@ -89,6 +95,415 @@ pub struct Metadata {
     pub extra_bindings: HashMap<String, ()>,
 
     /// If set, Juju magically determines that the charm is using v1 metadata
+    ///
+    /// Deprecated in favour of bases in charmcraft.yaml, see `effective_bases`
     #[serde(default)]
     pub series: Option<Vec<String>>,
+
+    /// Which user the charm's code runs as in its container
+    ///
+    /// Juju 3 only. Juju runs the charm as root if unset.
+    #[serde(default)]
+    pub charm_user: Option<CharmUser>,
+}
+
+/// Which user a sidecar charm's code runs as, see `Metadata::charm_user`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CharmUser {
+    /// The charm runs as root
+    Root,
+
+    /// The charm runs as a user that can use sudo
+    Sudoer,
+
+    /// The charm runs as an unprivileged user
+    NonRoot,
+}
+
+/// Ubuntu series names and the base channels they correspond to
+const SERIES_CHANNELS: &[(&str, &str)] = &[
+    ("trusty", "14.04"),
+    ("xenial", "16.04"),
+    ("bionic", "18.04"),
+    ("focal", "20.04"),
+    ("jammy", "22.04"),
+    ("noble", "24.04"),
+];
+
+impl Metadata {
+    /// Serializes the metadata as JSON
+    pub fn to_json(&self) -> Result<String, JujuError> {
+        Ok(to_string_pretty(self)?)
+    }
+
+    /// SHA-256 of the metadata's canonical form, e.g. `sha256:<hex>`, to detect changes
+    ///
+    /// Maps are hashed with their keys sorted, so reordering metadata.yaml doesn't change the
+    /// checksum.
+    pub fn checksum(&self) -> Result<String, JujuError> {
+        let mut canonical = String::new();
+        write_canonical(&serde_json::to_value(self)?, &mut canonical);
+
+        let digest = Digest::default();
+        let mut hasher = digest.hasher();
+        hasher.update(canonical.as_bytes());
+        Ok(digest.finish(hasher))
+    }
+
+    /// Maps the deprecated `series` list to the equivalent Ubuntu bases
+    ///
+    /// Unknown series are skipped with a warning.
+    pub fn effective_bases(&self) -> Vec<Base> {
+        self.series
+            .iter()
+            .flatten()
+            .filter_map(|series| {
+                let channel = SERIES_CHANNELS
+                    .iter()
+                    .find(|(name, _)| name == series)
+                    .map(|(_, channel)| channel.to_string());
+                if channel.is_none() {
+                    log::warn!("Unknown series `{}` in charm `{}`", series, self.name);
+                }
+                channel
+            })
+            .map(|channel| Base {
+                name: "ubuntu".into(),
+                channel,
+            })
+            .collect()
+    }
+
+    /// Names of resources that have no default and so must be supplied at deploy time
+    ///
+    /// oci-image resources with an `upstream-source` are optional to override, and everything
+    /// else is required. Sorted by name.
+    pub fn required_resources(&self) -> Vec<&str> {
+        let mut required: Vec<_> = self
+            .resources
+            .iter()
+            .filter(|(_, resource)| {
+                !matches!(
+                    resource,
+                    Resource::OciImage {
+                        upstream_source: Some(_),
+                        ..
+                    }
+                )
+            })
+            .map(|(name, _)| name.as_str())
+            .collect();
+
+        required.sort_unstable();
+        required
+    }
+
+    /// Each resource's declared origin, i.e. its `upstream-source`
+    ///
+    /// File resources, and oci-image resources without one, map to `None`.
+    pub fn resource_origins(&self) -> HashMap<String, Option<String>> {
+        self.resources
+            .iter()
+            .map(|(name, resource)| {
+                let origin = match resource {
+                    Resource::OciImage {
+                        upstream_source, ..
+                    } => upstream_source.clone(),
+                    Resource::File { .. } | Resource::Unknown => None,
+                };
+                (name.clone(), origin)
+            })
+            .collect()
+    }
+
+    /// Renames the charm, e.g. when forking it, and returns the old name
+    ///
+    /// Resources named after the charm, such as `foo-image` for charm `foo`, are renamed to
+    /// match, along with the container references to them. Bundles must then pass the new names
+    /// to `--resource`. If a renamed resource would replace one that already exists, nothing is
+    /// changed and `ResourceNameConflict` is returned.
+    pub fn rename(&mut self, new_name: &str) -> Result<String, JujuError> {
+        let prefix = format!("{}-", self.name);
+        let renamed = |name: &str| {
+            name.strip_prefix(&prefix)
+                .map(|rest| format!("{}-{}", new_name, rest))
+        };
+
+        // A resource that is renamed itself makes way for the one taking its name
+        for name in self.resources.keys() {
+            if let Some(new) = renamed(name) {
+                if self.resources.contains_key(&new) && renamed(&new).is_none() {
+                    return Err(JujuError::ResourceNameConflict(name.clone(), new));
+                }
+            }
+        }
+
+        self.resources = self
+            .resources
+            .drain(..)
+            .map(|(name, resource)| (renamed(&name).unwrap_or(name), resource))
+            .collect();
+
+        for container in self.containers.values_mut() {
+            if let Some(new) = container.resource().and_then(renamed) {
+                container.set_resource(new);
+            }
+        }
+
+        Ok(std::mem::replace(&mut self.name, new_name.into()))
+    }
+}
+
+/// Writes `value` as compact JSON with every object's keys sorted
+///
+/// serde_json's own maps only sort their keys without its `preserve_order` feature, which any
+/// crate in the dependency tree may turn on.
+fn write_canonical(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_unstable_by_key(|(key, _)| *key);
+
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::Value::from(key.as_str()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        serde_json::Value::Array(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(value, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::from_str;
+
+    use super::*;
+
+    #[test]
+    fn test_required_resources() {
+        let metadata: Metadata = from_str(
+            r#"
+name: foo
+summary: foo
+description: foo
+resources:
+  defaulted:
+    type: oci-image
+    upstream-source: foo/foo:latest
+  required:
+    type: oci-image
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(metadata.required_resources(), ["required"]);
+    }
+
+    #[test]
+    fn test_declaration_order() {
+        let metadata: Metadata = from_str(
+            r#"
+name: foo
+summary: foo
+description: foo
+requires:
+  zookeeper: {interface: zookeeper}
+  database: {interface: mysql}
+resources:
+  zeta-image: {type: oci-image}
+  alpha-image: {type: oci-image}
+"#,
+        )
+        .unwrap();
+
+        let requires: Vec<_> = metadata.requires.keys().collect();
+        assert_eq!(requires, ["zookeeper", "database"]);
+        let resources: Vec<_> = metadata.resources.keys().collect();
+        assert_eq!(resources, ["zeta-image", "alpha-image"]);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let metadata: Metadata = from_str(
+            r#"
+name: foo
+summary: foo
+description: foo
+provides:
+  website:
+    interface: http
+resources:
+  foo-image:
+    type: oci-image
+    upstream-source: foo/foo:latest
+extra-bindings:
+  data:
+"#,
+        )
+        .unwrap();
+
+        let json = metadata.to_json().unwrap();
+        assert_eq!(serde_json::from_str::<Metadata>(&json).unwrap(), metadata);
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut metadata: Metadata = from_str(
+            r#"
+name: foo
+summary: foo
+description: foo
+containers:
+  foo:
+    resource: foo-image
+  sidecar:
+    resource: sidecar-image
+resources:
+  foo-image:
+    type: oci-image
+  sidecar-image:
+    type: oci-image
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(metadata.rename("bar").unwrap(), "foo");
+        assert_eq!(metadata.name, "bar");
+
+        let mut resources: Vec<_> = metadata.resources.keys().collect();
+        resources.sort();
+        assert_eq!(resources, ["bar-image", "sidecar-image"]);
+        assert_eq!(metadata.containers["foo"].resource(), Some("bar-image"));
+        assert_eq!(
+            metadata.containers["sidecar"].resource(),
+            Some("sidecar-image")
+        );
+
+        let mut conflicting: Metadata = from_str(
+            "name: foo\nsummary: foo\ndescription: foo\nresources:\n  foo-image:\n    type: oci-image\n  bar-image:\n    type: oci-image\n",
+        )
+        .unwrap();
+        let original = conflicting.clone();
+        assert!(matches!(
+            conflicting.rename("bar"),
+            Err(JujuError::ResourceNameConflict(old, new)) if old == "foo-image" && new == "bar-image"
+        ));
+        assert_eq!(conflicting, original);
+    }
+
+    #[test]
+    fn test_effective_bases() {
+        let metadata: Metadata = from_str(
+            "name: foo\nsummary: foo\ndescription: foo\nseries: [focal, jammy, hirsute]\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            metadata.effective_bases(),
+            vec![
+                Base {
+                    name: "ubuntu".into(),
+                    channel: "20.04".into()
+                },
+                Base {
+                    name: "ubuntu".into(),
+                    channel: "22.04".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resource_origins() {
+        let metadata: Metadata = from_str(
+            r#"
+name: foo
+summary: foo
+description: foo
+resources:
+  image:
+    type: oci-image
+    upstream-source: foo/foo:1.0
+  built:
+    type: oci-image
+  data:
+    type: file
+    filename: data.tar
+"#,
+        )
+        .unwrap();
+
+        let origins = metadata.resource_origins();
+        assert_eq!(origins.len(), 3);
+        assert_eq!(origins["image"].as_deref(), Some("foo/foo:1.0"));
+        assert_eq!(origins["built"], None);
+        assert_eq!(origins["data"], None);
+    }
+
+    #[test]
+    fn test_checksum() {
+        let metadata: Metadata = from_str(
+            r#"
+name: foo
+summary: foo
+description: foo
+requires:
+  db: {interface: pgsql}
+  cache: {interface: redis}
+resources:
+  image: {type: oci-image}
+  data: {type: file, filename: data.tar}
+"#,
+        )
+        .unwrap();
+        let reordered: Metadata = from_str(
+            r#"
+resources:
+  data: {type: file, filename: data.tar}
+  image: {type: oci-image}
+description: foo
+requires:
+  cache: {interface: redis}
+  db: {interface: pgsql}
+summary: foo
+name: foo
+"#,
+        )
+        .unwrap();
+
+        let checksum = metadata.checksum().unwrap();
+        assert!(checksum.starts_with("sha256:"));
+        assert_eq!(checksum, reordered.checksum().unwrap());
+
+        let mut renamed = metadata.clone();
+        renamed.rename("bar").unwrap();
+        assert_ne!(checksum, renamed.checksum().unwrap());
+    }
+
+    #[test]
+    fn test_write_canonical() {
+        let value: serde_json::Value =
+            serde_json::from_str(r#"{"b": [{"d": 1, "c": null}], "a": "x"}"#).unwrap();
+        let mut canonical = String::new();
+        write_canonical(&value, &mut canonical);
+
+        assert_eq!(canonical, r#"{"a":"x","b":[{"c":null,"d":1}]}"#);
+    }
 }
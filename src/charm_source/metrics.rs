@@ -0,0 +1,35 @@
+use std::collections::HashMap;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// How a metric's value is interpreted
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MetricType {
+    /// The value is an absolute count, and may go up or down
+    Gauge,
+
+    /// The value is a count since the last time the metric was collected
+    Absolute,
+}
+
+/// A metric collected from a metered charm
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Metric {
+    #[serde(rename = "type")]
+    pub kind: MetricType,
+
+    /// What the metric measures
+    pub description: String,
+}
+
+/// A charm's metrics.yaml file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Metrics {
+    /// Metrics by name
+    ///
+    /// Built-in metrics such as `juju-units` are declared without a definition
+    pub metrics: HashMap<String, Option<Metric>>,
+}
@@ -1,36 +1,205 @@
+pub mod actions;
 pub mod charmcraft;
 pub mod config;
 pub mod container;
 pub mod device;
+pub mod library;
+pub mod manifest;
 pub mod metadata;
+pub mod metrics;
 pub mod relation;
+pub mod report;
+pub mod requirement;
 pub mod resource;
 pub mod storage;
+mod yaml;
 
-pub use charmcraft::{Base, BaseSpec, Charmcraft};
+pub use actions::{Action, Actions};
+pub use charmcraft::{Base, BaseSpec, Charmcraft, Verbosity};
 pub use config::{Config, ConfigOption};
-pub use container::{BaseContainer, Container, ContainerBase, ContainerMount, ResourceContainer};
-pub use metadata::Metadata;
-pub use relation::{Relation, RelationScope};
-pub use resource::Resource;
+pub use container::{
+    BaseContainer, Container, ContainerBase, ContainerMount, ExtendedContainer, ResourceContainer,
+};
+pub use library::CharmLibrary;
+pub use manifest::{Analysis, AnalysisAttribute, Manifest, ManifestBase};
+pub use metadata::{CharmUser, Metadata};
+pub use metrics::{Metric, MetricType, Metrics};
+pub use relation::{Interface, Relation, RelationScope};
+pub use report::{ResourceProgress, UploadReport};
+pub use requirement::Requirement;
+pub use resource::{ImageBuild, ResolutionOrder, Resource, ResourceDrift};
 pub use storage::Storage;
 
 use std::collections::HashMap;
 use std::env::current_dir;
-use std::io::Read;
-use std::path::PathBuf;
-use std::str::from_utf8;
+use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use ex::fs::{read, File};
+use ex::fs::{read, read_dir, remove_dir_all, File};
+use indexmap::IndexMap;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use semver::Version;
+use serde::de::DeserializeOwned;
 use serde_derive::{Deserialize, Serialize};
 use serde_yaml::from_slice;
+use tempfile::{NamedTempFile, TempDir};
+use zip::result::ZipError;
 use zip::ZipArchive;
 
-use crate::charm_url::CharmURL;
-use crate::cmd;
+use crate::channel::Channel;
+use crate::charm_url::{normalize_architecture, CharmURL};
+use crate::charmhub::{CharmRevision, ReleaseStatus, ResourceRevision, Status, StatusCache};
+use crate::cmd::{self, CommandRunner, Runner};
+use crate::digest::Digest;
 use crate::error::JujuError;
+use crate::toolchain;
+
+/// Where to open a charm from, see `CharmSource::open`
+#[derive(Debug, Clone, PartialEq)]
+pub enum CharmReference {
+    /// A source directory or built `.charm`
+    Path(PathBuf),
+
+    /// A charm URL, either local such as `./foo.charm` or in a store such as `ch:foo`
+    URL(CharmURL),
+}
+
+impl From<PathBuf> for CharmReference {
+    fn from(path: PathBuf) -> Self {
+        CharmReference::Path(path)
+    }
+}
+
+impl From<&Path> for CharmReference {
+    fn from(path: &Path) -> Self {
+        CharmReference::Path(path.into())
+    }
+}
+
+impl From<CharmURL> for CharmReference {
+    fn from(url: CharmURL) -> Self {
+        CharmReference::URL(url)
+    }
+}
+
+/// Fields of metadata.yaml that are valid, but that `Metadata` doesn't model yet
+///
+/// Strict loading accepts these rather than rejecting otherwise valid charms.
+const UNMODELLED_METADATA_FIELDS: &[&str] = &[
+    "assumes",
+    "display-name",
+    "docs",
+    "issues",
+    "maintainer",
+    "min-juju-version",
+    "source",
+    "tags",
+    "website",
+];
+
+/// How strictly `CharmSource::load_with` treats the charm's files
+///
+/// Loading is strict by default. Lenient loading is opt-in, e.g. to just read a charm's name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// Reject unknown fields in metadata.yaml, config.yaml, metrics.yaml, and actions.yaml, and
+    /// require charmcraft.yaml to exist
+    ///
+    /// charmcraft.yaml is never checked for unknown fields, since only its bases are modelled.
+    pub strict: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self { strict: true }
+    }
+}
+
+impl LoadOptions {
+    /// Parses config.yaml, which may be empty or only hold comments when freshly scaffolded
+    fn parse_config(&self, bytes: &[u8]) -> Result<Option<Config>, JujuError> {
+        let is_empty = String::from_utf8_lossy(bytes).lines().all(|line| {
+            let line = line.trim();
+            line.is_empty() || line.starts_with('#')
+        });
+
+        if is_empty {
+            return Ok(Some(Config {
+                options: IndexMap::new(),
+            }));
+        }
+
+        // Explain mistyped defaults better than the YAML error would
+        self.parse("config.yaml", bytes)
+            .map_err(|err| match Config::typecheck_defaults(bytes) {
+                Err(invalid @ JujuError::InvalidDefault(..)) => invalid,
+                _ => err,
+            })
+    }
+
+    /// Parses actions.yaml, where actions without parameters may have an empty body, e.g.
+    /// `restart:`
+    fn parse_actions(&self, bytes: &[u8]) -> Result<Option<Actions>, JujuError> {
+        let actions: Option<IndexMap<String, Option<Action>>> =
+            self.parse("actions.yaml", bytes)?;
+
+        Ok(actions.map(|actions| {
+            actions
+                .into_iter()
+                .map(|(name, action)| (name, action.unwrap_or_default()))
+                .collect()
+        }))
+    }
+
+    fn parse<T: DeserializeOwned>(&self, file: &str, bytes: &[u8]) -> Result<T, JujuError> {
+        if !self.strict {
+            return Ok(from_slice(bytes)?);
+        }
+
+        let mut unknown = None;
+        let parsed =
+            serde_ignored::deserialize(serde_yaml::Deserializer::from_slice(bytes), |path| {
+                let path = path.to_string();
+                let top_level = path.split('.').next().unwrap_or_default();
+                let allowed =
+                    file == "metadata.yaml" && UNMODELLED_METADATA_FIELDS.contains(&top_level);
+
+                if !allowed && unknown.is_none() {
+                    unknown = Some(path);
+                }
+            })?;
+
+        match unknown {
+            Some(path) => Err(JujuError::UnknownField(file.into(), path)),
+            None => Ok(parsed),
+        }
+    }
+}
+
+/// Registries that images can be pulled from without credentials
+pub const PUBLIC_REGISTRIES: &[&str] = &[
+    "docker.io",
+    "registry-1.docker.io",
+    "ghcr.io",
+    "quay.io",
+    "gcr.io",
+    "registry.k8s.io",
+    "k8s.gcr.io",
+    "public.ecr.aws",
+    "registry.jujucharms.com",
+];
 
 /// A charm, as represented by the source directory
+///
+/// Loading and static analysis only read the charm's files, and never run `charmcraft`,
+/// `docker`, or anything else that might need the network. That covers `validate`,
+/// `is_buildable`, `unbacked_containers`, `libraries`, `readme`, `manifest`, and the
+/// resource helpers. Building, uploading, and anything to do with Charmhub statuses do run
+/// external commands.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct CharmSource {
     /// The path to the charm's source code
@@ -44,191 +213,4097 @@ pub struct CharmSource {
 
     /// The charm's charmcraft.yaml file
     pub charmcraft: Charmcraft,
+
+    /// The charm's metrics.yaml file, for metered charms
+    pub metrics: Option<Metrics>,
+
+    /// The charm's actions.yaml file, if it has actions
+    pub actions: Option<Actions>,
+
+    /// Used to run `charmcraft` and friends
+    #[serde(skip)]
+    pub(crate) runner: Runner,
+
+    /// Statuses fetched by `status_cached`
+    #[serde(skip)]
+    status_cache: StatusCache,
+
+    /// Where uploads put scratch files, instead of the system temp directory
+    #[serde(skip)]
+    temp_dir: Option<PathBuf>,
+
+    /// The installed charmcraft's version, if known, to pick which output formats to ask for
+    #[serde(skip)]
+    charmcraft_version: Option<Version>,
+
+    /// Name to publish the charm under on Charmhub, if not `metadata.name`
+    #[serde(skip)]
+    publish_as: Option<String>,
+
+    /// Temporary directory that `source` lives in, if the charm owns it
+    #[serde(skip)]
+    scratch: ScratchDir,
+
+    /// The packed charm, if it was loaded from a reader rather than from `source`
+    #[serde(skip)]
+    packed: PackedCharm,
+}
+
+/// A temporary directory that's removed once the last charm referring to it is dropped
+///
+/// Like `cmd::Runner`, it never takes part in equality comparisons.
+#[derive(Debug, Clone, Default)]
+struct ScratchDir {
+    _dir: Option<Arc<TempDir>>,
 }
 
+impl PartialEq for ScratchDir {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// The bytes of a packed charm that's only held in memory, shared between clones
+///
+/// Like `ScratchDir`, it never takes part in equality comparisons.
+#[derive(Clone, Default)]
+struct PackedCharm {
+    bytes: Option<Arc<[u8]>>,
+}
+
+impl std::fmt::Debug for PackedCharm {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("PackedCharm")
+            .field("len", &self.bytes.as_ref().map(|bytes| bytes.len()))
+            .finish()
+    }
+}
+
+impl PartialEq for PackedCharm {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// A reader that zip archives can be opened from, whether a file or in memory
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
 impl CharmSource {
-    fn load_dir<P: Into<PathBuf>>(source: P) -> Result<Self, JujuError> {
+    fn load_dir<P: Into<PathBuf>>(source: P, options: &LoadOptions) -> Result<Self, JujuError> {
         let source = source.into();
         let config: Option<Config> = read(source.join("config.yaml"))
-            .map(|bytes| from_slice(&bytes))
+            .map(|bytes| options.parse_config(&bytes))
+            .unwrap_or(Ok(None))?;
+        let metadata = options.parse("metadata.yaml", &read(source.join("metadata.yaml"))?)?;
+        let charmcraft = match read(source.join("charmcraft.yaml")) {
+            Ok(bytes) => from_slice(&bytes)?,
+            Err(_) if !options.strict => Charmcraft::default(),
+            Err(err) => return Err(err.into()),
+        };
+        let metrics: Option<Metrics> = read(source.join("metrics.yaml"))
+            .map(|bytes| options.parse("metrics.yaml", &bytes))
+            .unwrap_or(Ok(None))?;
+        let actions: Option<Actions> = read(source.join("actions.yaml"))
+            .map(|bytes| options.parse_actions(&bytes))
             .unwrap_or(Ok(None))?;
-        let metadata = from_slice(&read(source.join("metadata.yaml"))?)?;
-        let charmcraft = from_slice(&read(source.join("charmcraft.yaml"))?)?;
 
         Ok(Self {
             source,
             config,
             metadata,
             charmcraft,
+            metrics,
+            actions,
+            runner: Runner::default(),
+            status_cache: StatusCache::default(),
+            temp_dir: None,
+            charmcraft_version: None,
+            publish_as: None,
+            scratch: ScratchDir::default(),
+            packed: PackedCharm::default(),
         })
     }
 
-    fn load_zip<P: Into<PathBuf>>(source: P) -> Result<Self, JujuError> {
+    fn load_zip<P: Into<PathBuf>>(source: P, options: &LoadOptions) -> Result<Self, JujuError> {
         let source = source.into();
         let mut archive = ZipArchive::new(File::open(&source)?)?;
-        let config: Option<Config> = archive
-            .by_name("config.yaml")
-            .map(|mut zf| -> Result<_, JujuError> {
-                let mut buf = String::new();
-                zf.read_to_string(&mut buf)?;
-                Ok(from_slice(buf.as_bytes())?)
-            })
+
+        Self::load_archive(source, &mut archive, options)
+    }
+
+    fn load_archive<R: Read + Seek>(
+        source: PathBuf,
+        archive: &mut ZipArchive<R>,
+        options: &LoadOptions,
+    ) -> Result<Self, JujuError> {
+        let config: Option<Config> = zip_entry(archive, "config.yaml")?
+            .map(|bytes| options.parse_config(&bytes))
             .unwrap_or(Ok(None))?;
 
-        let metadata = {
-            let mut zf = archive.by_name("metadata.yaml")?;
-            let mut buf = String::new();
-            zf.read_to_string(&mut buf)?;
-            from_slice(buf.as_bytes())?
+        let metadata = match zip_entry(archive, "metadata.yaml")? {
+            Some(bytes) => options.parse("metadata.yaml", &bytes)?,
+            None => return Err(ZipError::FileNotFound.into()),
         };
 
-        let charmcraft = {
-            let mut zf = archive.by_name("charmcraft.yaml")?;
-            let mut buf = String::new();
-            zf.read_to_string(&mut buf)?;
-            from_slice(buf.as_bytes())?
+        let charmcraft = match zip_entry(archive, "charmcraft.yaml")? {
+            Some(bytes) => from_slice(&bytes)?,
+            None if !options.strict => Charmcraft::default(),
+            None => return Err(ZipError::FileNotFound.into()),
         };
 
+        let metrics: Option<Metrics> = zip_entry(archive, "metrics.yaml")?
+            .map(|bytes| options.parse("metrics.yaml", &bytes))
+            .unwrap_or(Ok(None))?;
+
+        let actions: Option<Actions> = zip_entry(archive, "actions.yaml")?
+            .map(|bytes| options.parse_actions(&bytes))
+            .unwrap_or(Ok(None))?;
+
         Ok(Self {
             source,
             config,
             metadata,
             charmcraft,
+            metrics,
+            actions,
+            runner: Runner::default(),
+            status_cache: StatusCache::default(),
+            temp_dir: None,
+            charmcraft_version: None,
+            publish_as: None,
+            scratch: ScratchDir::default(),
+            packed: PackedCharm::default(),
         })
     }
 
-    /// Load a charm from its source directory
+    /// Load a packed charm from `reader`, e.g. a download that was never written to disk
+    ///
+    /// The charm has no source path. Files are read from the archive, which is kept in memory
+    /// and shared between the charm's clones.
+    pub fn from_reader<R: Read + Seek>(reader: R) -> Result<Self, JujuError> {
+        let mut archive = ZipArchive::new(reader)?;
+        let mut charm = Self::load_archive(PathBuf::new(), &mut archive, &LoadOptions::default())?;
+
+        let mut reader = archive.into_inner();
+        let mut bytes = vec![];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_to_end(&mut bytes)?;
+        charm.packed = PackedCharm {
+            bytes: Some(bytes.into()),
+        };
+        Ok(charm)
+    }
+
+    /// Opens the charm's archive, or returns `None` if it's a source directory
+    fn archive(&self) -> Result<Option<ZipArchive<Box<dyn ReadSeek>>>, JujuError> {
+        let reader: Box<dyn ReadSeek> = match &self.packed.bytes {
+            Some(bytes) => Box::new(Cursor::new(bytes.clone())),
+            None if self.source.is_file() => Box::new(File::open(&self.source)?),
+            None => return Ok(None),
+        };
+
+        Ok(Some(ZipArchive::new(reader)?))
+    }
+
+    /// Runs `charmcraft`, `docker`, `juju` and friends through `runner` instead of as
+    /// subprocesses
+    ///
+    /// Everything from building to uploading and releasing goes through it, e.g. to script the
+    /// tools' responses in tests, or to run them in a sandbox.
+    pub fn with_runner(mut self, runner: Arc<dyn CommandRunner>) -> Self {
+        self.runner = Runner::new(runner);
+        self
+    }
+
+    /// Puts scratch files created while uploading under `dir`, e.g. on a volume with more space
+    /// than the system temp directory
+    pub fn with_temp_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+
+    /// Tells the charm which charmcraft is installed, e.g. from `Toolchain::charmcraft_version`
+    ///
+    /// Without it, subcommands are tried with `--format json` first, falling back to their text
+    /// output if charmcraft doesn't recognize the flag.
+    pub fn with_charmcraft_version(mut self, version: Version) -> Self {
+        self.charmcraft_version = Some(version);
+        self
+    }
+
+    /// Uploads and releases the charm as `name` instead of its metadata name, e.g. for a fork
+    /// registered separately on Charmhub
+    pub fn with_publish_as(mut self, name: Option<&str>) -> Self {
+        self.publish_as = name.map(String::from);
+        self
+    }
+
+    /// The name that the charm is published under on Charmhub
+    fn publish_name(&self) -> &str {
+        self.publish_as.as_deref().unwrap_or(&self.metadata.name)
+    }
+
+    /// Load a charm from its source directory or a built `.charm`
+    ///
+    /// Unknown fields and a missing charmcraft.yaml are rejected, see `load_lenient` to
+    /// tolerate them.
     pub fn load<P: Into<PathBuf>>(source: P) -> Result<Self, JujuError> {
+        Self::load_with(source, &LoadOptions::default())
+    }
+
+    /// Opens a charm from a path or a charm URL
+    ///
+    /// Store charms are downloaded with `juju download` into a temporary directory first, which
+    /// needs the `download` feature. Without it they're rejected with `RemoteCharm`. The
+    /// directory is removed once the charm and its clones are dropped.
+    pub fn open(reference: impl Into<CharmReference>) -> Result<Self, JujuError> {
+        Self::open_with_runner(reference, Arc::new(cmd::SystemRunner))
+    }
+
+    /// Like `open`, but runs `juju download` and everything the charm runs later through
+    /// `runner`, see `with_runner`
+    pub fn open_with_runner(
+        reference: impl Into<CharmReference>,
+        runner: Arc<dyn CommandRunner>,
+    ) -> Result<Self, JujuError> {
+        let charm = match reference.into() {
+            CharmReference::Path(path) => Self::load(path)?,
+            CharmReference::URL(url) => match url.as_local_path() {
+                Some(path) => Self::load(path)?,
+                None => Self::download(&url, &Runner::new(runner.clone()))?,
+            },
+        };
+
+        Ok(charm.with_runner(runner))
+    }
+
+    #[cfg(feature = "download")]
+    fn download(url: &CharmURL, runner: &Runner) -> Result<Self, JujuError> {
+        let dir = tempfile::Builder::new().prefix("juju-rs").tempdir()?;
+        let path = dir.path().join(format!("{}.charm", url.name));
+        runner.run(
+            "juju",
+            &[
+                "download".into(),
+                url.to_string(),
+                "--filepath".into(),
+                path.to_string_lossy().into_owned(),
+            ],
+        )?;
+
+        let mut charm = Self::load(path)?;
+        charm.scratch = ScratchDir {
+            _dir: Some(Arc::new(dir)),
+        };
+        Ok(charm)
+    }
+
+    #[cfg(not(feature = "download"))]
+    fn download(url: &CharmURL, _runner: &Runner) -> Result<Self, JujuError> {
+        Err(JujuError::RemoteCharm(url.to_string()))
+    }
+
+    /// Load a charm from its source directory or a built `.charm`, as configured by `options`
+    pub fn load_with<P: Into<PathBuf>>(
+        source: P,
+        options: &LoadOptions,
+    ) -> Result<Self, JujuError> {
         let source = source.into();
         if source.is_file() {
-            Self::load_zip(source)
+            Self::load_zip(source, options)
         } else {
-            Self::load_dir(source)
+            Self::load_dir(source, options)
         }
     }
 
-    /// Build the charm from its source directory
-    pub fn build(&self, destructive_mode: bool) -> Result<(), JujuError> {
-        let source = self.source.to_string_lossy();
-        let mut args = vec!["pack", "-p", &source];
+    /// Reads just the metadata of the charm at `source`, a source directory or built `.charm`
+    ///
+    /// Much cheaper than `load` when scanning many charms for e.g. their names. Charms without
+    /// a metadata.yaml have their metadata read from charmcraft.yaml instead.
+    pub fn load_metadata_only(source: &Path) -> Result<Metadata, JujuError> {
+        if let Some(bytes) = read_charm_file(source, Path::new("metadata.yaml"))? {
+            return Ok(from_slice(&bytes)?);
+        }
 
-        if destructive_mode {
-            args.push("--destructive-mode")
+        match read_charm_file(source, Path::new("charmcraft.yaml"))? {
+            Some(bytes) => Ok(from_slice(&bytes)?),
+            None if source.is_file() => Err(ZipError::FileNotFound.into()),
+            None => Err(std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} not found", source.join("metadata.yaml").display()),
+            )
+            .into()),
         }
+    }
 
-        cmd::run("charmcraft", &args)
+    /// Load a charm while tolerating unknown fields and a missing charmcraft.yaml
+    ///
+    /// Useful for inspecting charms that are slightly off, e.g. to just read their name.
+    pub fn load_lenient<P: Into<PathBuf>>(source: P) -> Result<Self, JujuError> {
+        Self::load_with(source, &LoadOptions { strict: false })
     }
 
-    pub fn artifact_path(&self) -> CharmURL {
-        let mut path = current_dir().unwrap();
-        let base = &self.charmcraft.bases[0].build_on[0];
-        let arch = self
-            .charmcraft
-            .architectures
-            .get(0)
+    /// Clones a charm from a git repository into `dest` and loads it
+    ///
+    /// `git_ref` may be a branch, tag, or commit, and defaults to the remote's default branch. If
+    /// anything goes wrong, `dest` is removed again.
+    pub fn from_git(url: &str, git_ref: Option<&str>, dest: &Path) -> Result<Self, JujuError> {
+        Self::from_git_with_runner(url, git_ref, dest, Arc::new(cmd::SystemRunner))
+    }
+
+    /// Like `from_git`, but runs `git` and everything the charm runs later through `runner`,
+    /// see `with_runner`
+    pub fn from_git_with_runner(
+        url: &str,
+        git_ref: Option<&str>,
+        dest: &Path,
+        runner: Arc<dyn CommandRunner>,
+    ) -> Result<Self, JujuError> {
+        let existed = dest.exists();
+        let dest_str = dest.to_string_lossy().into_owned();
+        let git = Runner::new(runner.clone());
+
+        let loaded = git
+            .get_output(
+                "git",
+                &[
+                    "clone".into(),
+                    "--quiet".into(),
+                    "--".into(),
+                    url.into(),
+                    dest_str.clone(),
+                ],
+            )
+            .and_then(|_| match git_ref {
+                Some(git_ref) => git.get_output(
+                    "git",
+                    &[
+                        "-C".into(),
+                        dest_str.clone(),
+                        "checkout".into(),
+                        "--quiet".into(),
+                        git_ref.into(),
+                    ],
+                ),
+                None => Ok(vec![]),
+            })
+            .and_then(|_| Self::load(dest))
+            .map(|charm| charm.with_runner(runner));
+
+        if loaded.is_err() && !existed {
+            let _ = remove_dir_all(dest);
+        }
+
+        loaded
+    }
+
+    /// A compact description of the charm for CLI listings
+    ///
+    /// Formatted as `name — summary (N relations, M resources)`.
+    pub fn one_line(&self) -> String {
+        let md = &self.metadata;
+        let relations = md.provides.len() + md.requires.len() + md.peer.len();
+
+        format!(
+            "{} — {} ({} relations, {} resources)",
+            md.name,
+            md.summary,
+            relations,
+            md.resources.len()
+        )
+    }
+
+    /// Names of the metrics that the charm reports, sorted
+    pub fn metric_names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self
+            .metrics
+            .iter()
+            .flat_map(|m| m.metrics.keys())
             .map(String::as_str)
-            .unwrap_or("amd64");
+            .collect();
 
-        path.push(&format!(
-            "{}_{}-{}-{}.charm",
-            self.metadata.name, base.name, base.channel, arch
-        ));
-        CharmURL::from_path(path)
+        names.sort_unstable();
+        names
     }
 
-    pub fn upload_charmhub(
-        &self,
-        resources: &HashMap<String, String>,
-        to: &[String],
-        destructive_mode: bool,
-    ) -> Result<String, JujuError> {
-        self.build(destructive_mode)?;
+    /// Reads a file from within the charm, whether it's a source directory or a built `.charm`
+    ///
+    /// Returns `None` if the file doesn't exist.
+    pub fn read_file(&self, relative: &Path) -> Result<Option<Vec<u8>>, JujuError> {
+        match self.archive()? {
+            Some(mut archive) => zip_entry(&mut archive, &relative.to_string_lossy()),
+            None => read_charm_file(&self.source, relative),
+        }
+    }
+
+    /// Hashes the charm's files with `digest`, returning e.g. `sha256:<hex>`
+    ///
+    /// Covers the paths and contents of every file, so the hash changes if a file is added,
+    /// removed, renamed, or edited. Build artifacts and `.git` are ignored in source directories.
+    pub fn content_hash(&self, digest: Digest) -> Result<String, JujuError> {
+        let mut hasher = digest.hasher();
+
+        for path in self.source_files()? {
+            let contents = self.read_file(Path::new(&path))?.unwrap_or_default();
+            hasher.update(path.as_bytes());
+            hasher.update(&[0]);
+            hasher.update(&(contents.len() as u64).to_be_bytes());
+            hasher.update(&contents);
+        }
+
+        Ok(digest.finish(hasher))
+    }
+
+    /// Paths of the charm's files, relative to its root and sorted
+    fn source_files(&self) -> Result<Vec<String>, JujuError> {
+        let mut paths: Vec<String> = if let Some(archive) = self.archive()? {
+            archive
+                .file_names()
+                .filter(|name| !name.ends_with('/'))
+                .map(String::from)
+                .collect()
+        } else {
+            let mut paths = vec![];
+            find_files(&self.source, Path::new(""), &mut paths)?;
+            paths.retain(|path| {
+                let top = Path::new(path).components().next();
+                let top = top.map(|c| c.as_os_str().to_string_lossy().into_owned());
+                !matches!(top.as_deref(), Some("build") | Some(".git")) && !path.ends_with(".charm")
+            });
+            paths
+        };
+
+        paths.sort_unstable();
+        Ok(paths)
+    }
+
+    /// Checks whether the source has changed since `artifact` was built from it
+    ///
+    /// Files that were packed into the artifact are compared by contents, and any others (such as
+    /// files excluded from the build) by modification time against the artifact's.
+    pub fn is_stale(&self, artifact: &Path) -> Result<bool, JujuError> {
+        let built = std::fs::metadata(artifact)?.modified()?;
+        let mut archive = ZipArchive::new(File::open(artifact)?)?;
 
-        let resources = self.resources_with_defaults(resources)?;
-
-        let resources: Vec<_> = resources
-            .iter()
-            .filter_map(|(name, value)| {
-                let res = self.metadata.resources.get(name).expect("Must exist!");
-
-                match res {
-                    Resource::OciImage { .. } => {
-                        cmd::run(
-                            "charmcraft",
-                            &[
-                                "upload-resource",
-                                &self.metadata.name,
-                                name,
-                                "--image",
-                                value,
-                            ],
-                        )
-                        .unwrap();
-
-                        let output = cmd::get_output(
-                            "charmcraft",
-                            &["resource-revisions", &self.metadata.name, name],
-                        )
-                        .unwrap();
-                        let output = String::from_utf8_lossy(&output);
-                        let revision = output.lines().nth(1).unwrap().split(' ').next().unwrap();
-
-                        Some(format!("--resource={}:{}", name, revision))
+        for path in self.source_files()? {
+            let contents = self.read_file(Path::new(&path))?.unwrap_or_default();
+            match zip_entry(&mut archive, &path)? {
+                Some(packed) if packed != contents => return Ok(true),
+                Some(_) => {}
+                None => {
+                    if std::fs::metadata(self.source.join(&path))?.modified()? > built {
+                        return Ok(true);
                     }
-                    Resource::File { .. } => None,
                 }
-            })
-            .collect();
+            }
+        }
 
-        let args: Vec<_> = vec![
-            "upload".into(),
-            "--quiet".into(),
-            self.artifact_path().to_string(),
-        ]
-        .into_iter()
-        .chain(to.iter().map(|ch| format!("--release={}", ch)))
-        .chain(resources)
-        .collect();
+        Ok(false)
+    }
 
-        let mut output = cmd::get_output("charmcraft", &args)?;
-        output.drain(0..9);
-        output.truncate(output.iter().position(|&x| x == 0x20).unwrap());
-        let revision = from_utf8(&output).unwrap().parse::<u32>().unwrap();
+    /// Points the oci-image resource `resource` at `new_source`, e.g. to bump its image
+    ///
+    /// Only the `upstream-source` line of metadata.yaml, or of charmcraft.yaml for charms that
+    /// keep their metadata there, is rewritten, so comments and key order are kept. The loaded
+    /// `metadata` isn't updated; load the charm again to see the change.
+    pub fn set_resource_source(&self, resource: &str, new_source: &str) -> Result<(), JujuError> {
+        if !matches!(
+            self.metadata.resources.get(resource),
+            Some(Resource::OciImage { .. })
+        ) {
+            return Err(JujuError::ResourceNotFound(
+                resource.into(),
+                self.metadata.name.clone(),
+            ));
+        }
 
-        Ok(CharmURL::parse(&self.metadata.name)
-            .unwrap()
-            .with_revision(Some(revision))
-            .to_string())
+        let path = ["metadata.yaml", "charmcraft.yaml"]
+            .iter()
+            .map(|file| self.source.join(file))
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                JujuError::CannotEdit(
+                    self.source.display().to_string(),
+                    "not a source directory".into(),
+                )
+            })?;
+
+        let text = String::from_utf8(read(&path)?)
+            .map_err(|err| JujuError::CannotEdit(path.display().to_string(), err.to_string()))?;
+        let edited = yaml::set_scalar(
+            &text,
+            &["resources", resource, "upstream-source"],
+            &serde_yaml::Value::from(new_source),
+        )
+        .map_err(|reason| JujuError::CannotEdit(path.display().to_string(), reason))?;
+
+        std::fs::write(&path, edited)?;
+        Ok(())
     }
 
-    /// Merge default resources with resources given in e.g. a bundle.yaml
-    pub fn resources_with_defaults(
+    /// Reads the charm's README.md, which Charmhub shows on the charm's page
+    pub fn readme(&self) -> Result<Option<String>, JujuError> {
+        Ok(self
+            .read_file(Path::new("README.md"))?
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+    }
+
+    /// Finds the charm libraries vendored under `lib/charms`, sorted by charm and name
+    pub fn libraries(&self) -> Result<Vec<CharmLibrary>, JujuError> {
+        let paths = if let Some(archive) = self.archive()? {
+            archive
+                .file_names()
+                .filter(|name| name.starts_with("lib/charms/"))
+                .map(String::from)
+                .collect()
+        } else {
+            let mut paths = vec![];
+            find_files(&self.source, Path::new("lib/charms"), &mut paths)?;
+            paths
+        };
+
+        let mut libraries = vec![];
+        for path in paths {
+            if let Some(source) = self.read_file(Path::new(&path))? {
+                libraries.extend(CharmLibrary::parse(
+                    &path,
+                    &String::from_utf8_lossy(&source),
+                ));
+            }
+        }
+
+        libraries.sort_by(|a, b| (&a.charm, &a.name).cmp(&(&b.charm, &b.name)));
+        Ok(libraries)
+    }
+
+    /// Reads the Python packages that the charm depends on from its `requirements.txt`
+    ///
+    /// Files included with `-r` are followed, relative to the including file. Comments and other
+    /// pip options are skipped. Returns an empty list if there's no `requirements.txt`.
+    pub fn python_requirements(&self) -> Result<Vec<Requirement>, JujuError> {
+        let mut requirements = vec![];
+        let mut visited = vec![];
+        self.read_requirements(
+            Path::new("requirements.txt"),
+            &mut visited,
+            &mut requirements,
+        )?;
+        Ok(requirements)
+    }
+
+    fn read_requirements(
         &self,
-        configured: &HashMap<String, String>,
-    ) -> Result<HashMap<String, String>, JujuError> {
-        self.metadata
-            .resources
-            .iter()
-            .map(|(k, v)| -> Result<(String, String), JujuError> {
-                if let Some(c) = configured.get(k) {
-                    return Ok((k.clone(), c.clone()));
+        path: &Path,
+        visited: &mut Vec<PathBuf>,
+        requirements: &mut Vec<Requirement>,
+    ) -> Result<(), JujuError> {
+        if visited.iter().any(|v| v == path) {
+            return Ok(());
+        }
+        visited.push(path.into());
+
+        let contents = match self.read_file(path)? {
+            Some(contents) => String::from_utf8_lossy(&contents).replace("\\\n", " "),
+            None => return Ok(()),
+        };
+
+        for line in contents.lines() {
+            let line = line.trim();
+            let include = line
+                .strip_prefix("-r")
+                .or_else(|| line.strip_prefix("--requirement"))
+                .map(|rest| rest.trim_start_matches('=').trim());
+
+            match include {
+                Some(included) if !included.is_empty() => {
+                    let included = path.parent().unwrap_or(Path::new("")).join(included);
+                    self.read_requirements(&included, visited, requirements)?;
                 }
+                _ => requirements.extend(Requirement::parse(line)),
+            }
+        }
 
-                match v {
-                    Resource::OciImage {
-                        upstream_source: Some(us),
-                        ..
-                    } => Ok((k.clone(), us.clone())),
-                    Resource::OciImage { .. } => Err(JujuError::ResourceNotFound(
-                        k.clone(),
-                        self.metadata.name.clone(),
-                    )),
-                    Resource::File { .. } => Err(JujuError::ResourceNotFound(
-                        k.clone(),
+        Ok(())
+    }
+
+    /// Whether `other` declares the same charm, e.g. a rebuild compared to a released `.charm`
+    ///
+    /// Compares metadata (including resources), config, metrics, and actions, regardless of
+    /// the order they were declared in. Where each charm was loaded from, and anything that only
+    /// changes per build such as manifest.yaml, is ignored.
+    pub fn is_equivalent(&self, other: &CharmSource) -> bool {
+        self.metadata == other.metadata
+            && self.config == other.config
+            && self.metrics == other.metrics
+            && self.actions == other.actions
+    }
+
+    /// Reads the manifest.yaml that charmcraft adds when packing, if this is a built charm
+    pub fn manifest(&self) -> Result<Option<Manifest>, JujuError> {
+        self.read_file(Path::new("manifest.yaml"))?
+            .map(|bytes| from_slice(&bytes))
+            .transpose()
+            .map_err(JujuError::from)
+    }
+
+    /// Reads the store revision recorded in the built `.charm` at `path`, if there is one
+    ///
+    /// Looks for a `revision` key in `manifest.yaml`, then for the legacy `revision` file that
+    /// the store adds to downloaded charms.
+    pub fn embedded_revision(path: &Path) -> Result<Option<u32>, JujuError> {
+        if let Some(manifest) = read_zip_entry(path, "manifest.yaml")? {
+            let manifest: serde_yaml::Value = from_slice(&manifest)?;
+            if let Some(revision) = manifest.get("revision").and_then(|r| r.as_u64()) {
+                return Ok(Some(revision as u32));
+            }
+        }
+
+        Ok(read_zip_entry(path, "revision")?
+            .and_then(|bytes| String::from_utf8_lossy(&bytes).trim().parse().ok()))
+    }
+
+    /// Checks that the charm is a source directory that `build` can pack
+    ///
+    /// The directory needs both metadata.yaml and charmcraft.yaml, and an entry point that
+    /// charmcraft recognizes: `src/charm.py` for the operator framework, `layer.yaml` for
+    /// reactive charms, or a `dispatch` script or `hooks` directory.
+    pub fn is_buildable(&self) -> Result<(), JujuError> {
+        let not_buildable = |reason: &str| {
+            JujuError::NotBuildable(self.source.display().to_string(), reason.into())
+        };
+
+        if !self.source.is_dir() {
+            return Err(not_buildable("it isn't a source directory"));
+        }
+
+        for file in &["metadata.yaml", "charmcraft.yaml"] {
+            if !self.source.join(file).is_file() {
+                return Err(not_buildable(&format!("{} is missing", file)));
+            }
+        }
+
+        let entry_points = ["src/charm.py", "layer.yaml", "dispatch", "hooks"];
+        if !entry_points.iter().any(|e| self.source.join(e).exists()) {
+            return Err(not_buildable(
+                "no src/charm.py, layer.yaml, dispatch, or hooks found",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks the charm for common packaging mistakes
+    ///
+    /// Each config option's default must have the option's type, and each container must have
+    /// exactly one of `resource` or `bases`, and only subordinate charms may require
+    /// container-scoped relations. Principals may still provide them, e.g. `nrpe-external-master`.
+    /// Operator framework charms, i.e. those with a
+    /// `src/charm.py`, must ship a `dispatch` script. In source directories, it must also be
+    /// executable.
+    pub fn validate(&self) -> Result<(), JujuError> {
+        if let Some(config) = self.read_file(Path::new("config.yaml"))? {
+            Config::typecheck_defaults(&config)?;
+        }
+
+        let mut containers: Vec<_> = self.metadata.containers.iter().collect();
+        containers.sort_unstable_by_key(|(name, _)| *name);
+        for (name, container) in containers {
+            let reason = match (container.resource(), container.bases()) {
+                (Some(_), Some(_)) => "it has both a resource and bases",
+                (None, None) => "it has neither a resource nor bases",
+                _ => continue,
+            };
+            return Err(JujuError::InvalidContainer(name.clone(), reason.into()));
+        }
+
+        if !self.metadata.subordinate {
+            for (name, relation) in &self.metadata.requires {
+                if relation.scope == Some(RelationScope::Container) {
+                    return Err(JujuError::InvalidRelationScope(
                         self.metadata.name.clone(),
-                    )),
+                        name.clone(),
+                    ));
                 }
-            })
-            .collect()
+            }
+        }
+
+        if self.read_file(Path::new("src/charm.py"))?.is_none() {
+            return Ok(());
+        }
+
+        let missing = || JujuError::MissingDispatch(self.metadata.name.clone());
+
+        if self.source.is_file() || self.packed.bytes.is_some() {
+            return match self.read_file(Path::new("dispatch"))? {
+                Some(_) => Ok(()),
+                None => Err(missing()),
+            };
+        }
+
+        let dispatch = self.source.join("dispatch");
+        if !dispatch.is_file() {
+            return Err(missing());
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if std::fs::metadata(&dispatch)?.permissions().mode() & 0o111 == 0 {
+                return Err(missing());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Unpacks the built `.charm` at `path` into `dest`, creating it if needed
+    ///
+    /// File modes are kept, so that e.g. `dispatch` stays executable. Entries that would land
+    /// outside of `dest` are skipped.
+    pub fn extract(path: &Path, dest: &Path) -> Result<(), JujuError> {
+        extract_archive(&mut ZipArchive::new(File::open(path)?)?, dest)
+    }
+
+    /// Copies the charm to `dest` and loads the copy, e.g. to patch it without touching the
+    /// original
+    ///
+    /// Source directories are copied without any built `.charm` files or `build` directory.
+    /// Built charms are extracted.
+    pub fn clone_to(&self, dest: &Path) -> Result<CharmSource, JujuError> {
+        match self.archive()? {
+            Some(mut archive) => extract_archive(&mut archive, dest)?,
+            None => copy_source(&self.source, dest)?,
+        }
+
+        let mut cloned = Self::load_lenient(dest)?;
+        cloned.runner = self.runner.clone();
+        cloned.temp_dir = self.temp_dir.clone();
+        cloned.charmcraft_version = self.charmcraft_version.clone();
+        cloned.publish_as = self.publish_as.clone();
+        Ok(cloned)
+    }
+
+    /// Build the charm from its source directory
+    pub fn build(&self, destructive_mode: bool) -> Result<(), JujuError> {
+        self.pack(destructive_mode, None)
+    }
+
+    /// Like `build`, but with charmcraft logging at `verbosity`, e.g. to debug a failing build
+    pub fn build_with_verbosity(
+        &self,
+        destructive_mode: bool,
+        verbosity: Verbosity,
+    ) -> Result<(), JujuError> {
+        self.pack(destructive_mode, Some(verbosity))
+    }
+
+    fn pack(&self, destructive_mode: bool, verbosity: Option<Verbosity>) -> Result<(), JujuError> {
+        let mut args = vec![
+            "pack".to_string(),
+            "-p".to_string(),
+            self.source.to_string_lossy().to_string(),
+        ];
+
+        if destructive_mode {
+            args.push("--destructive-mode".into())
+        }
+
+        if let Some(verbosity) = verbosity {
+            args.push(format!("--verbosity={}", verbosity.as_str()));
+        }
+
+        self.runner.run("charmcraft", &args)
+    }
+
+    /// Returns the commit checked out in the charm's source directory, if it's a git repo
+    fn git_commit(&self) -> Option<String> {
+        let args = [
+            "-C".to_string(),
+            self.source.to_string_lossy().to_string(),
+            "rev-parse".to_string(),
+            "HEAD".to_string(),
+        ];
+        let output = self.runner.get_output("git", &args).ok()?;
+
+        Some(String::from_utf8_lossy(&output).trim().to_string()).filter(|c| !c.is_empty())
+    }
+
+    /// Path of the `.charm` file that `build` produces for the first entry in `bases`
+    ///
+    /// Fails with `ArtifactNotFound` if charmcraft.yaml doesn't declare any bases.
+    pub fn artifact_path(&self) -> Result<CharmURL, JujuError> {
+        self.artifact_paths()
+            .into_iter()
+            .next()
+            .map(CharmURL::from_path)
+            .ok_or_else(|| JujuError::ArtifactNotFound(self.metadata.name.clone()))
+    }
+
+    /// Paths of the `.charm` files that `build` produces, one for each entry in `bases`
+    pub fn artifact_paths(&self) -> Vec<PathBuf> {
+        let dir = current_dir().unwrap();
+        let arch = self
+            .charmcraft
+            .architectures
+            .first()
+            .map(|arch| normalize_architecture(arch))
+            .unwrap_or("amd64");
+
+        self.charmcraft
+            .bases
+            .iter()
+            .map(|spec| {
+                let base = &spec.build_on[0];
+                dir.join(format!(
+                    "{}_{}-{}-{}.charm",
+                    self.metadata.name, base.name, base.channel, arch
+                ))
+            })
+            .collect()
+    }
+
+    /// Finds the `.charm` file that `build` produced in the current directory
+    ///
+    /// Prefers the path from `artifact_paths`, falling back to the most recently modified
+    /// `<name>_*.charm` file if charmcraft named it differently.
+    pub fn find_artifact(&self) -> Result<PathBuf, JujuError> {
+        self.find_artifact_in(&current_dir()?)
+    }
+
+    fn find_artifact_in(&self, dir: &Path) -> Result<PathBuf, JujuError> {
+        if let Some(path) = self.artifact_paths().into_iter().next() {
+            let path = dir.join(path.file_name().unwrap());
+            if path.exists() {
+                return Ok(path);
+            }
+        }
+
+        let prefix = format!("{}_", self.metadata.name);
+        let exact = format!("{}.charm", self.metadata.name);
+        let mut newest = None;
+
+        for entry in read_dir(dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+
+            let matches = file_name.starts_with(&prefix) && file_name.ends_with(".charm");
+            if !matches && file_name != exact {
+                continue;
+            }
+
+            let modified = entry.metadata()?.modified()?;
+            if newest.as_ref().map(|(m, _)| modified > *m).unwrap_or(true) {
+                newest = Some((modified, entry.path()));
+            }
+        }
+
+        newest
+            .map(|(_, path)| path)
+            .ok_or_else(|| JujuError::ArtifactNotFound(self.metadata.name.clone()))
+    }
+
+    /// Builds the charm and uploads it and its resources to Charmhub
+    ///
+    /// Only `charmcraft` is used, so nothing depends on the legacy charm store or `charm` snap.
+    /// Charms with several bases have the `.charm` of each base uploaded, then each released.
+    /// File resources may be given as `archive:<path>!<entry>` to upload a file from within
+    /// another archive, such as a `.charm`.
+    pub fn upload_charmhub(
+        &self,
+        resources: &HashMap<String, String>,
+        to: &[String],
+        destructive_mode: bool,
+    ) -> Result<UploadReport, JujuError> {
+        self.upload_charmhub_with_progress(resources, to, destructive_mode, &|_| {})
+    }
+
+    /// Registers the charm's name if it isn't yet, then builds, uploads and releases it to
+    /// `channels` with `upload_charmhub`
+    ///
+    /// Registering a name that's already registered is not an error, so this can be run for
+    /// every release, including the first.
+    pub fn publish(
+        &self,
+        channels: &[String],
+        resources: &HashMap<String, String>,
+        destructive_mode: bool,
+    ) -> Result<UploadReport, JujuError> {
+        let registered = self.runner.get_output(
+            "charmcraft",
+            &["register".into(), self.publish_name().to_string()],
+        );
+        match registered {
+            Ok(_) => log::info!("Registered {}", self.publish_name()),
+            Err(JujuError::SubcommandError(_, stderr)) if stderr.contains("already") => {
+                log::debug!("{} is already registered", self.publish_name());
+            }
+            Err(err) => return Err(err),
+        }
+
+        self.upload_charmhub(resources, channels, destructive_mode)
+    }
+
+    /// Like `upload_charmhub`, but reports how each resource upload is progressing
+    ///
+    /// Updates are only sent when the percentage goes up. Output that doesn't look like
+    /// progress is ignored.
+    pub fn upload_charmhub_with_progress(
+        &self,
+        resources: &HashMap<String, String>,
+        to: &[String],
+        destructive_mode: bool,
+        on_progress: &dyn Fn(ResourceProgress),
+    ) -> Result<UploadReport, JujuError> {
+        let started = Instant::now();
+
+        self.build(destructive_mode)?;
+
+        let resources = self.build_images(resources)?;
+        let resources = self.resources_with_defaults(&resources)?;
+
+        let mut resource_revisions = HashMap::new();
+
+        for (name, value) in &resources {
+            let res = self.metadata.resources.get(name).expect("Must exist!");
+
+            // File resources may be taken from inside another archive, in which case they're
+            // extracted to a temporary file that lives until the upload is done
+            let (flag, source, _extracted) = match res {
+                Resource::OciImage { .. } => ("--image", value.clone(), None),
+                Resource::File { .. } => {
+                    match extract_archive_entry(value, self.temp_dir.as_deref())? {
+                        Some(file) => (
+                            "--filepath",
+                            file.path().to_string_lossy().into_owned(),
+                            Some(file),
+                        ),
+                        None => ("--filepath", value.clone(), None),
+                    }
+                }
+                Resource::Unknown => continue,
+            };
+
+            // Resuming an interrupted upload shouldn't push the same image again
+            if let Resource::OciImage { .. } = res {
+                if let Some(revision) = self.uploaded_image_revision(name, value) {
+                    resource_revisions.insert(name.clone(), revision);
+                    continue;
+                }
+            }
+
+            let mut last = None;
+            self.runner.run_streaming(
+                "charmcraft",
+                &[
+                    "upload-resource".into(),
+                    self.publish_name().to_string(),
+                    name.clone(),
+                    flag.into(),
+                    source,
+                ],
+                &mut |line| {
+                    if let Some(progress) = ResourceProgress::parse(name, line) {
+                        if last.is_none_or(|l| progress.percent > l) {
+                            last = Some(progress.percent);
+                            on_progress(progress);
+                        }
+                    }
+                },
+            )?;
+
+            let revision = ResourceRevision::latest(&self.uploaded_resource_revisions(name)?)
+                .ok_or_else(|| {
+                    JujuError::ResourceNotFound(name.clone(), self.publish_name().to_string())
+                })?;
+
+            // Images are available to every architecture unless told otherwise
+            if let (Resource::OciImage { .. }, Some(arches)) = (res, self.image_architectures()) {
+                self.runner.run(
+                    "charmcraft",
+                    &[
+                        "set-resource-architectures".into(),
+                        self.publish_name().to_string(),
+                        name.clone(),
+                        format!("--revision={}", revision),
+                        arches.join(","),
+                    ],
+                )?;
+            }
+
+            resource_revisions.insert(name.clone(), revision);
+        }
+
+        let resource_args: Vec<_> = resource_revisions
+            .iter()
+            .map(|(name, revision)| format!("--resource={}:{}", name, revision))
+            .collect();
+        let mut artifacts = self.artifact_paths();
+        if artifacts.is_empty() {
+            artifacts.push(self.find_artifact()?);
+        }
+
+        // A single artifact is released as it's uploaded. Several are all uploaded before any is
+        // released, so that a failed upload doesn't leave the channels with only some bases.
+        let mut base_revisions = HashMap::new();
+        let mut revisions = vec![];
+        for artifact in &artifacts {
+            let mut args = vec![
+                "upload".to_string(),
+                "--quiet".into(),
+                artifact.to_string_lossy().into(),
+            ];
+            if let Some(name) = &self.publish_as {
+                args.push(format!("--name={}", name));
+            }
+            if artifacts.len() == 1 {
+                args.extend(to.iter().map(|ch| format!("--release={}", ch)));
+                args.extend(resource_args.iter().cloned());
+            }
+
+            let revision = parse_upload_revision(&self.runner.get_output("charmcraft", &args)?)?;
+            base_revisions.insert(self.artifact_base(artifact), self.revision_url(revision));
+            revisions.push(revision);
+        }
+
+        // `charmcraft release` takes a single revision, so each is released on its own
+        if artifacts.len() > 1 && !to.is_empty() {
+            for revision in &revisions {
+                let args: Vec<_> = vec![
+                    "release".to_string(),
+                    self.publish_name().to_string(),
+                    format!("--revision={}", revision),
+                ]
+                .into_iter()
+                .chain(to.iter().map(|ch| format!("--channel={}", ch)))
+                .chain(resource_args.iter().cloned())
+                .collect();
+                self.runner.run("charmcraft", &args)?;
+            }
+        }
+
+        let revision = revisions.first().copied().unwrap_or_default();
+
+        Ok(UploadReport {
+            charm: self.publish_name().to_string(),
+            revision_url: self.revision_url(revision),
+            base_revisions,
+            resource_revisions,
+            channels: to.to_vec(),
+            git_commit: self.git_commit(),
+            duration_secs: started.elapsed().as_secs_f64(),
+        })
+    }
+
+    /// The revision of `resource` that was uploaded from the same image as the local `image`
+    ///
+    /// Images are matched by their registry digests, as the local image ID differs from what was
+    /// uploaded. Returns `None` if that can't be determined, e.g. because docker doesn't know the
+    /// image or the uploaded revisions can't be listed.
+    fn uploaded_image_revision(&self, resource: &str, image: &str) -> Option<u32> {
+        let inspected = self
+            .runner
+            .get_output(
+                "docker",
+                &[
+                    "image".into(),
+                    "inspect".into(),
+                    "--format={{json .RepoDigests}}".into(),
+                    image.into(),
+                ],
+            )
+            .ok()?;
+        let repo_digests: Vec<String> = serde_json::from_slice(&inspected).ok()?;
+        let digests: Vec<_> = repo_digests
+            .iter()
+            .filter_map(|d| d.rsplit_once('@'))
+            .map(|(_, digest)| digest)
+            .collect();
+        if digests.is_empty() {
+            return None;
+        }
+
+        self.uploaded_resource_revisions(resource)
+            .ok()?
+            .into_iter()
+            .find(|r| matches!(&r.digest, Some(d) if digests.contains(&d.as_str())))
+            .map(|r| r.revision)
+    }
+
+    /// Lists the uploaded revisions of `resource`
+    fn uploaded_resource_revisions(
+        &self,
+        resource: &str,
+    ) -> Result<Vec<ResourceRevision>, JujuError> {
+        self.charmcraft_formatted(
+            &[
+                "resource-revisions".into(),
+                self.publish_name().to_string(),
+                resource.into(),
+            ],
+            ResourceRevision::parse_all,
+            ResourceRevision::parse_table,
+        )
+    }
+
+    /// Lists every uploaded revision of the charm registered as `name`, released or not
+    fn uploaded_revisions(&self, name: &str) -> Result<Vec<CharmRevision>, JujuError> {
+        self.charmcraft_formatted(
+            &["revisions".into(), name.into()],
+            CharmRevision::parse_all,
+            CharmRevision::parse_table,
+        )
+    }
+
+    /// Runs `charmcraft` with `args`, asking for JSON output if it supports that
+    ///
+    /// Older charmcraft only prints tables, so its output is parsed with `text` instead. If the
+    /// version isn't known, JSON is tried first and text is the fallback.
+    fn charmcraft_formatted<T>(
+        &self,
+        args: &[String],
+        json: impl Fn(&[u8]) -> Result<T, JujuError>,
+        text: impl Fn(&str) -> Result<T, JujuError>,
+    ) -> Result<T, JujuError> {
+        let supports_json = self
+            .charmcraft_version
+            .as_ref()
+            .map(toolchain::supports_json_format);
+
+        if supports_json != Some(false) {
+            let mut json_args = args.to_vec();
+            json_args.extend(["--format".into(), "json".into()]);
+
+            match self.runner.get_output("charmcraft", &json_args) {
+                Ok(output) => return json(&output),
+                Err(err) if supports_json.is_none() && is_unrecognized_argument(&err) => {
+                    log::debug!("charmcraft doesn't support --format, falling back to text");
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let output = self.runner.get_output("charmcraft", args)?;
+        text(&String::from_utf8_lossy(&output))
+    }
+
+    /// Charm URL of `revision` of the charm, e.g. `foo-42`
+    fn revision_url(&self, revision: u32) -> String {
+        CharmURL::parse(self.publish_name())
+            .unwrap()
+            .with_revision(Some(revision))
+            .to_string()
+    }
+
+    /// The base that a `.charm` from `artifact_paths` was built for, e.g. `ubuntu-20.04-amd64`
+    fn artifact_base(&self, artifact: &Path) -> String {
+        let file_name = artifact.file_name().unwrap_or_default().to_string_lossy();
+        let prefix = format!("{}_", self.metadata.name);
+
+        file_name
+            .trim_start_matches(prefix.as_str())
+            .trim_end_matches(".charm")
+            .to_string()
+    }
+
+    /// Architectures that uploaded oci-image resources should be limited to
+    ///
+    /// Taken from the `architectures` in charmcraft.yaml. `None` means every architecture.
+    pub fn image_architectures(&self) -> Option<Vec<String>> {
+        let mut arches: Vec<_> = self
+            .charmcraft
+            .architectures
+            .iter()
+            .map(|arch| normalize_architecture(arch).to_string())
+            .collect();
+
+        if arches.is_empty() {
+            return None;
+        }
+
+        arches.sort();
+        arches.dedup();
+        Some(arches)
+    }
+
+    /// Fetches the Charmhub release status of the charm registered as `name`
+    pub fn status(&self, name: &str) -> Result<Status, JujuError> {
+        let output = self.runner.get_output(
+            "charmcraft",
+            &[
+                "status".into(),
+                name.into(),
+                "--format".into(),
+                "json".into(),
+            ],
+        )?;
+
+        Status::parse(&output)
+    }
+
+    /// The revision after the highest one released to any channel of the charm registered as
+    /// `name`, or 1 if nothing has been released yet
+    pub fn next_revision(&self, name: &str) -> Result<u32, JujuError> {
+        Ok(self.status(name)?.max_revision().map_or(1, |r| r + 1))
+    }
+
+    /// Like `status`, but reuses a status fetched within the last 30 seconds
+    ///
+    /// Useful for operations such as bundle promotion that query the same charm repeatedly.
+    /// Call `clear_status_cache` to force the next lookup to hit Charmhub.
+    pub fn status_cached(&self, name: &str) -> Result<Status, JujuError> {
+        if let Some(status) = self.status_cache.get(name) {
+            return Ok(status);
+        }
+
+        let status = self.status(name)?;
+        self.status_cache.insert(name, status.clone());
+        Ok(status)
+    }
+
+    /// Forgets every status remembered by `status_cached`
+    pub fn clear_status_cache(&self) {
+        self.status_cache.clear();
+    }
+
+    /// Polls Charmhub until `revision` of the charm registered as `name` can be queried
+    ///
+    /// Freshly uploaded revisions can take a moment to show up, so failures to list the
+    /// revisions are retried until `timeout` elapses. Revisions are found whether they're
+    /// released or not, e.g. to promote one right after uploading it.
+    pub fn wait_for_revision(
+        &self,
+        name: &str,
+        revision: u32,
+        timeout: Duration,
+    ) -> Result<(), JujuError> {
+        self.wait_for_revision_every(name, revision, timeout, Duration::from_secs(2))
+    }
+
+    fn wait_for_revision_every(
+        &self,
+        name: &str,
+        revision: u32,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<(), JujuError> {
+        let start = Instant::now();
+
+        loop {
+            match self.uploaded_revisions(name) {
+                Ok(revisions) if revisions.iter().any(|r| r.revision == revision) => return Ok(()),
+                Ok(_) | Err(JujuError::SubcommandError(..)) => {}
+                Err(err) => return Err(err),
+            }
+
+            if start.elapsed() + interval > timeout {
+                return Err(JujuError::Timeout(format!(
+                    "{} revision {}",
+                    name, revision
+                )));
+            }
+
+            sleep(interval);
+        }
+    }
+
+    /// Returns the resource revisions released alongside the charm revision in `channel`
+    ///
+    /// Closed channels have no resources, so they return an empty map.
+    pub fn resource_revisions(
+        &self,
+        name: &str,
+        channel: &Channel,
+    ) -> Result<HashMap<String, u32>, JujuError> {
+        let status = self.status_cached(name)?;
+
+        Ok(status
+            .release(channel)
+            .filter(|r| r.status == ReleaseStatus::Open)
+            .and_then(|r| r.resources.as_ref())
+            .map(|resources| {
+                resources
+                    .iter()
+                    .map(|r| (r.name.clone(), r.revision))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Compares the declared `upstream-source` of each oci-image resource with what's released
+    /// to `channel`
+    ///
+    /// A resource has drifted if it isn't released at all, or if the store reports a different
+    /// origin for the released revision. Resources whose origin isn't known are assumed to match.
+    /// Sorted by resource name.
+    pub fn resource_drift(
+        &self,
+        name: &str,
+        channel: &Channel,
+    ) -> Result<Vec<ResourceDrift>, JujuError> {
+        let status = self.status_cached(name)?;
+        let released = status
+            .release(channel)
+            .filter(|r| r.status == ReleaseStatus::Open)
+            .and_then(|r| r.resources.as_ref());
+
+        let mut drift: Vec<_> = self
+            .metadata
+            .resources
+            .iter()
+            .filter_map(|(resource, declared)| {
+                let declared = match declared {
+                    Resource::OciImage {
+                        upstream_source, ..
+                    } => upstream_source.clone(),
+                    _ => return None,
+                };
+                let released = released.into_iter().flatten().find(|r| &r.name == resource);
+
+                let drifted = match released {
+                    None => true,
+                    Some(r) => r.origin.is_some() && r.origin != declared,
+                };
+
+                Some(ResourceDrift {
+                    resource: resource.clone(),
+                    declared,
+                    released_revision: released.map(|r| r.revision),
+                    released_origin: released.and_then(|r| r.origin.clone()),
+                })
+                .filter(|_| drifted)
+            })
+            .collect();
+
+        drift.sort_by(|a, b| a.resource.cmp(&b.resource));
+        Ok(drift)
+    }
+
+    /// Releases the revision currently in `from` into `to`, along with its resources
+    ///
+    /// Refuses to replace a newer revision in `to` with an older one, unless `allow_downgrade`
+    /// is set. Returns the revision that was promoted.
+    pub fn promote(
+        &self,
+        name: &str,
+        from: &Channel,
+        to: &Channel,
+        allow_downgrade: bool,
+    ) -> Result<u32, JujuError> {
+        let status = self.status(name)?;
+        let release = status
+            .release(from)
+            .filter(|r| r.status == ReleaseStatus::Open)
+            .ok_or_else(|| JujuError::NoRelease(name.into(), from.to_string()))?;
+        let revision = release
+            .revision
+            .ok_or_else(|| JujuError::NoRelease(name.into(), from.to_string()))?;
+
+        let current = status
+            .release(to)
+            .filter(|r| r.status == ReleaseStatus::Open)
+            .and_then(|r| r.revision);
+        if let Some(current) = current {
+            if current > revision && !allow_downgrade {
+                return Err(JujuError::WouldDowngrade(
+                    name.into(),
+                    to.to_string(),
+                    current,
+                    revision,
+                ));
+            }
+        }
+
+        let args: Vec<_> = vec![
+            "release".into(),
+            name.into(),
+            format!("--revision={}", revision),
+            format!("--channel={}", to),
+        ]
+        .into_iter()
+        .chain(
+            release
+                .resources
+                .iter()
+                .flatten()
+                .map(|r| format!("--resource={}:{}", r.name, r.revision)),
+        )
+        .collect();
+
+        self.runner.run("charmcraft", &args)?;
+        self.clear_status_cache();
+
+        Ok(revision)
+    }
+
+    /// Builds oci-image resources that declare a `build`, unless they're set in `configured`
+    ///
+    /// Returns `configured` with the tag of each built image added.
+    pub fn build_images(
+        &self,
+        configured: &HashMap<String, String>,
+    ) -> Result<HashMap<String, String>, JujuError> {
+        let mut resources = configured.clone();
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        for (name, resource) in &self.metadata.resources {
+            let build = match resource {
+                Resource::OciImage {
+                    build: Some(build), ..
+                } if !configured.contains_key(name) => build,
+                _ => continue,
+            };
+
+            let tag = format!("{}-{}:{}", self.metadata.name, name, stamp);
+            self.runner.run(
+                "docker",
+                &[
+                    "build".into(),
+                    "-t".into(),
+                    tag.clone(),
+                    "-f".into(),
+                    self.source.join(&build.dockerfile).to_string_lossy().into(),
+                    self.source.join(&build.context).to_string_lossy().into(),
+                ],
+            )?;
+
+            resources.insert(name.clone(), tag);
+        }
+
+        Ok(resources)
+    }
+
+    /// Copies every oci-image resource into `target_registry`, e.g. for airgapped deployments
+    ///
+    /// Images are taken from `resources`, falling back to each resource's `upstream-source`, and
+    /// keep their repository path and tag under the new registry. Returns the new references.
+    pub fn mirror_images(
+        &self,
+        resources: &HashMap<String, String>,
+        target_registry: &str,
+    ) -> Result<HashMap<String, String>, JujuError> {
+        let mut mirrored = HashMap::new();
+
+        for (name, resource) in &self.metadata.resources {
+            let image = match (resource, resources.get(name)) {
+                (Resource::OciImage { .. }, Some(image)) => image,
+                (
+                    Resource::OciImage {
+                        upstream_source: Some(us),
+                        ..
+                    },
+                    None,
+                ) => us,
+                (Resource::OciImage { .. }, None) => {
+                    return Err(JujuError::ResourceNotFound(
+                        name.clone(),
+                        self.metadata.name.clone(),
+                    ))
+                }
+                _ => continue,
+            };
+
+            let target = format!(
+                "{}/{}",
+                target_registry.trim_end_matches('/'),
+                strip_registry(image)
+            );
+
+            self.runner.run("docker", &["pull".into(), image.clone()])?;
+            self.runner
+                .run("docker", &["tag".into(), image.clone(), target.clone()])?;
+            self.runner
+                .run("docker", &["push".into(), target.clone()])?;
+
+            mirrored.insert(name.clone(), target);
+        }
+
+        Ok(mirrored)
+    }
+
+    /// Checks `options` set for the charm in e.g. a bundle against the charm's config
+    ///
+    /// Reports every option that the charm doesn't have or that has the wrong type, sorted by
+    /// option name.
+    pub fn validate_bundle_options(
+        &self,
+        options: &HashMap<String, serde_yaml::Value>,
+    ) -> Result<(), Vec<JujuError>> {
+        let mut names: Vec<_> = options.keys().collect();
+        names.sort_unstable();
+
+        let errors: Vec<_> = names
+            .into_iter()
+            .filter_map(|name| {
+                match &self.config {
+                    Some(config) => config.validate_value(name, &options[name]),
+                    None => Err(JujuError::UnknownConfigOption(name.clone())),
+                }
+                .err()
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Checks that the image of each oci-image resource exists, with `docker manifest inspect`
+    ///
+    /// Images come from `resources`, or else the resource's `upstream-source`. Resources with
+    /// neither are skipped, see `unbacked_containers`. This talks to registries, so it's not part
+    /// of `validate`. Reports every unresolvable image, sorted by resource name.
+    pub fn check_images(&self, resources: &HashMap<String, String>) -> Result<(), Vec<JujuError>> {
+        let mut images = vec![];
+        for (name, res) in &self.metadata.resources {
+            let image = match (resources.get(name), res) {
+                (Some(image), Resource::OciImage { .. }) => image.clone(),
+                (
+                    None,
+                    Resource::OciImage {
+                        upstream_source: Some(us),
+                        ..
+                    },
+                ) => interpolate(us, &|var| std::env::var(var).ok()).map_err(|err| vec![err])?,
+                _ => continue,
+            };
+            images.push((name, image));
+        }
+        images.sort_unstable();
+
+        let errors: Vec<_> = images
+            .into_iter()
+            .filter_map(|(name, image)| {
+                self.runner
+                    .get_output(
+                        "docker",
+                        &["manifest".into(), "inspect".into(), image.clone()],
+                    )
+                    .err()
+                    .map(|err| {
+                        let reason = match err {
+                            JujuError::SubcommandError(_, stderr) => stderr.trim().to_string(),
+                            err => err.to_string(),
+                        };
+                        JujuError::UnresolvableImage(name.clone(), image.clone(), reason)
+                    })
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Runs `action` on unit `unit` of the deployed application `app`, waiting for it to finish
+    ///
+    /// The action and `params` are checked against the charm's actions.yaml first: required
+    /// parameters must be given, and unknown ones are rejected if the action disallows
+    /// `additionalProperties`. Returns the action's results, e.g. for integration tests.
+    pub fn run_action(
+        &self,
+        app: &str,
+        unit: u32,
+        action: &str,
+        params: &HashMap<String, String>,
+    ) -> Result<serde_yaml::Value, JujuError> {
+        let spec = self
+            .actions
+            .as_ref()
+            .and_then(|actions| actions.get(action))
+            .ok_or_else(|| JujuError::UnknownAction(self.metadata.name.clone(), action.into()))?;
+        let invalid = |reason: String| JujuError::InvalidActionParams(action.into(), reason);
+
+        if let Some(missing) = spec
+            .required_params()
+            .into_iter()
+            .find(|name| !params.contains_key(*name))
+        {
+            return Err(invalid(format!("`{}` is required", missing)));
+        }
+
+        let mut names: Vec<_> = params.keys().collect();
+        names.sort_unstable();
+        if spec.additional_properties == Some(false) {
+            if let Some(unknown) = names.iter().find(|name| !spec.params.contains_key(**name)) {
+                return Err(invalid(format!("`{}` is not a parameter", unknown)));
+            }
+        }
+
+        let target = format!("{}/{}", app, unit);
+        let args: Vec<_> = vec!["run".to_string(), target.clone(), action.into()]
+            .into_iter()
+            .chain(
+                names
+                    .iter()
+                    .map(|name| format!("{}={}", name, params[*name])),
+            )
+            .chain(vec!["--format".into(), "yaml".into(), "--wait".into()])
+            .collect();
+
+        let output: serde_yaml::Value = from_slice(&self.runner.get_output("juju", &args)?)?;
+
+        Ok(output
+            .get(target.as_str())
+            .and_then(|result| result.get("results"))
+            .cloned()
+            .unwrap_or_else(|| serde_yaml::Value::Mapping(Default::default())))
+    }
+
+    /// Containers whose oci-image resource has no default and isn't set in `resources`
+    ///
+    /// Deploying the charm would fail for these. Containers that only list `bases` need no
+    /// resource. Sorted by name.
+    pub fn unbacked_containers(&self, resources: &HashMap<String, String>) -> Vec<&str> {
+        let mut unbacked: Vec<_> = self
+            .metadata
+            .containers
+            .iter()
+            .filter(|(_, container)| {
+                let resource = match container.resource() {
+                    Some(resource) => resource,
+                    None => return false,
+                };
+
+                let has_default = matches!(
+                    self.metadata.resources.get(resource),
+                    Some(Resource::OciImage {
+                        upstream_source: Some(_),
+                        ..
+                    }) | Some(Resource::OciImage { build: Some(_), .. })
+                );
+
+                !has_default && !resources.contains_key(resource)
+            })
+            .map(|(name, _)| name.as_str())
+            .collect();
+
+        unbacked.sort_unstable();
+        unbacked
+    }
+
+    /// Registries of oci-image `upstream-source`s that aren't in `PUBLIC_REGISTRIES`
+    ///
+    /// Images from these will likely need credentials to be uploaded.
+    pub fn private_image_registries(&self) -> Vec<&str> {
+        self.private_image_registries_excluding(PUBLIC_REGISTRIES)
+    }
+
+    /// Registries of oci-image `upstream-source`s that aren't in `public`, sorted and deduplicated
+    pub fn private_image_registries_excluding(&self, public: &[&str]) -> Vec<&str> {
+        let mut registries: Vec<_> = self
+            .metadata
+            .resources
+            .values()
+            .filter_map(|resource| match resource {
+                Resource::OciImage {
+                    upstream_source: Some(us),
+                    ..
+                } => Some(registry(us)),
+                _ => None,
+            })
+            .filter(|r| !public.contains(r))
+            .collect();
+
+        registries.sort_unstable();
+        registries.dedup();
+        registries
+    }
+
+    /// Merge default resources with resources given in e.g. a bundle.yaml
+    ///
+    /// Configured resources take precedence over defaults, see `resources_with_order`. Sources
+    /// are used as is, see `resources_with_env` and `resources_with_vars` to expand `${VAR}`
+    /// references in them.
+    pub fn resources_with_defaults(
+        &self,
+        configured: &HashMap<String, String>,
+    ) -> Result<HashMap<String, String>, JujuError> {
+        self.resources_with_order(configured, ResolutionOrder::default())
+    }
+
+    /// Like `resources_with_defaults`, but with `order` deciding whether configured resources
+    /// or defaults take precedence
+    pub fn resources_with_order(
+        &self,
+        configured: &HashMap<String, String>,
+        order: ResolutionOrder,
+    ) -> Result<HashMap<String, String>, JujuError> {
+        self.resources_with_defaults_from(configured, None, order)
+    }
+
+    /// Like `resources_with_defaults`, but expands `${VAR}` references in a resource's
+    /// `upstream-source` from the process environment
+    ///
+    /// Referencing a variable that isn't set is an error.
+    pub fn resources_with_env(
+        &self,
+        configured: &HashMap<String, String>,
+    ) -> Result<HashMap<String, String>, JujuError> {
+        self.resources_with_defaults_from(
+            configured,
+            Some(&|var| std::env::var(var).ok()),
+            ResolutionOrder::default(),
+        )
+    }
+
+    /// Like `resources_with_env`, but expands `${VAR}` references from `vars` instead of the
+    /// process environment
+    pub fn resources_with_vars(
+        &self,
+        configured: &HashMap<String, String>,
+        vars: &HashMap<String, String>,
+    ) -> Result<HashMap<String, String>, JujuError> {
+        self.resources_with_defaults_from(
+            configured,
+            Some(&|var| vars.get(var).cloned()),
+            ResolutionOrder::default(),
+        )
+    }
+
+    fn resources_with_defaults_from(
+        &self,
+        configured: &HashMap<String, String>,
+        lookup: Option<VarLookup>,
+        order: ResolutionOrder,
+    ) -> Result<HashMap<String, String>, JujuError> {
+        self.metadata
+            .resources
+            .iter()
+            .map(|(k, v)| -> Result<(String, String), JujuError> {
+                let has_default = matches!(
+                    v,
+                    Resource::OciImage {
+                        upstream_source: Some(_),
+                        ..
+                    }
+                );
+                let prefer_default = order == ResolutionOrder::DefaultFirst && has_default;
+
+                if let (Some(c), false) = (configured.get(k), prefer_default) {
+                    return Ok((k.clone(), c.clone()));
+                }
+
+                match v {
+                    Resource::OciImage {
+                        upstream_source: Some(us),
+                        ..
+                    } => match lookup {
+                        Some(lookup) => Ok((k.clone(), interpolate(us, lookup)?)),
+                        None => Ok((k.clone(), us.clone())),
+                    },
+                    Resource::OciImage { .. } => Err(JujuError::ResourceNotFound(
+                        k.clone(),
+                        self.metadata.name.clone(),
+                    )),
+                    Resource::File { .. } | Resource::Unknown => Err(JujuError::ResourceNotFound(
+                        k.clone(),
+                        self.metadata.name.clone(),
+                    )),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Parses the revision out of `charmcraft upload` output such as `Revision 7 of 'foo' created`
+fn parse_upload_revision(output: &[u8]) -> Result<u32, JujuError> {
+    let output = String::from_utf8_lossy(output);
+
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Revision "))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|revision| revision.parse().ok())
+        .ok_or_else(|| {
+            JujuError::SubcommandError(
+                "charmcraft upload".into(),
+                format!("unexpected output `{}`", output.trim()),
+            )
+        })
+}
+
+/// Extracts the file that a reference such as `archive:other.charm!files/foo.tar` points at
+///
+/// The file is created in `temp_dir`, or the system temp directory if unset. Returns `None` for
+/// references that aren't of that form, such as plain paths.
+fn extract_archive_entry(
+    reference: &str,
+    temp_dir: Option<&Path>,
+) -> Result<Option<NamedTempFile>, JujuError> {
+    let (archive, entry) = match reference
+        .strip_prefix("archive:")
+        .and_then(|r| r.split_once('!'))
+    {
+        Some(parts) => parts,
+        None => return Ok(None),
+    };
+
+    let bytes = read_zip_entry(Path::new(archive), entry)?
+        .ok_or_else(|| JujuError::ArchiveEntryNotFound(entry.into(), archive.into()))?;
+
+    let mut file = match temp_dir {
+        Some(dir) => NamedTempFile::new_in(dir)?,
+        None => NamedTempFile::new()?,
+    };
+    file.write_all(&bytes)?;
+    Ok(Some(file))
+}
+
+/// Reads a file from within the charm at `source`, whether it's a directory or a `.charm`
+fn read_charm_file(source: &Path, relative: &Path) -> Result<Option<Vec<u8>>, JujuError> {
+    if source.is_file() {
+        read_zip_entry(source, &relative.to_string_lossy())
+    } else {
+        match std::fs::read(source.join(relative)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Unpacks `archive` into `dest`, see `CharmSource::extract`
+fn extract_archive<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    dest: &Path,
+) -> Result<(), JujuError> {
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let target = match entry.enclosed_name() {
+            Some(name) => dest.join(name),
+            None => continue,
+        };
+
+        if entry.is_dir() {
+            std::fs::create_dir_all(&target)?;
+            continue;
+        }
+
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::io::copy(&mut entry, &mut File::create(&target)?)?;
+
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&target, std::fs::Permissions::from_mode(mode))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the file called `name` from the zip at `path`, or `None` if it's not in the archive
+fn read_zip_entry(path: &Path, name: &str) -> Result<Option<Vec<u8>>, JujuError> {
+    zip_entry(&mut ZipArchive::new(File::open(path)?)?, name)
+}
+
+/// Whether `err` is a subcommand rejecting an argument it doesn't know, such as `--format` on
+/// an older charmcraft
+fn is_unrecognized_argument(err: &JujuError) -> bool {
+    match err {
+        JujuError::SubcommandError(_, stderr) => {
+            stderr.contains("unrecognized argument") || stderr.contains("no such option")
+        }
+        _ => false,
+    }
+}
+
+fn zip_entry<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Option<Vec<u8>>, JujuError> {
+    let mut zf = match archive.by_name(name) {
+        Ok(zf) => zf,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut buf = Vec::new();
+    zf.read_to_end(&mut buf)?;
+    Ok(Some(buf))
+}
+
+/// Collects the paths of the files under `relative` within `root`, relative to `root`
+fn find_files(root: &Path, relative: &Path, found: &mut Vec<String>) -> Result<(), JujuError> {
+    let entries = match std::fs::read_dir(root.join(relative)) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            find_files(root, &path, found)?;
+        } else {
+            found.push(path.to_string_lossy().into_owned());
+        }
+    }
+
+    Ok(())
+}
+
+/// Recursively copies the charm source directory `from` into `to`, skipping build artifacts
+///
+/// Only the top-level `build` directory and `.charm` files are charmcraft's, anything further
+/// down belongs to the charm.
+fn copy_source(from: &Path, to: &Path) -> Result<(), JujuError> {
+    std::fs::create_dir_all(to)?;
+
+    for entry in read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+        let target = to.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            if entry.file_name() != "build" {
+                copy_dir(&path, &target)?;
+            }
+        } else if path.extension().is_none_or(|ext| ext != "charm") {
+            std::fs::copy(&path, &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Recursively copies the directory `from` into `to`
+fn copy_dir(from: &Path, to: &Path) -> Result<(), JujuError> {
+    std::fs::create_dir_all(to)?;
+
+    for entry in read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Looks up the value of a variable referenced as `${VAR}`, or `None` if it's undefined
+type VarLookup<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// Expands `${VAR}` references in `value` using `lookup`
+///
+/// An unterminated `${` is kept as is.
+fn interpolate(value: &str, lookup: VarLookup) -> Result<String, JujuError> {
+    let mut expanded = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let var = &rest[start + 2..end];
+
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&lookup(var).ok_or_else(|| JujuError::UndefinedVariable(var.into()))?);
+        rest = &rest[end + 1..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Removes the registry host from an image reference such as `registry.example.com/foo/foo:1`
+///
+/// As with docker, the first path component is only treated as a host if it looks like one.
+fn strip_registry(image: &str) -> &str {
+    match image.split_once('/') {
+        Some((host, rest)) if is_registry_host(host) => rest,
+        _ => image,
+    }
+}
+
+/// The registry that an image reference points at, which is Docker Hub unless one is given
+fn registry(image: &str) -> &str {
+    match image.split_once('/') {
+        Some((host, _)) if is_registry_host(host) => host,
+        _ => "docker.io",
+    }
+}
+
+fn is_registry_host(component: &str) -> bool {
+    component.contains(['.', ':']) || component == "localhost"
+}
+
+/// Builds several charms at once, with at most `concurrency` builds running at a time
+///
+/// Returns the artifact paths of each charm, in the same order as `sources`.
+pub fn build_all(
+    sources: &[CharmSource],
+    destructive_mode: bool,
+    concurrency: usize,
+) -> Vec<Result<Vec<PathBuf>, JujuError>> {
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(concurrency.max(1))
+        .build()
+        .expect("Couldn't create build thread pool");
+
+    pool.install(|| {
+        sources
+            .par_iter()
+            .map(|charm| {
+                charm.build(destructive_mode)?;
+                Ok(charm.artifact_paths())
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    use ex::fs::write;
+    use serde_json::{from_str, json, Value};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::charmhub;
+    use crate::cmd::mock::MockRunner;
+    use crate::testing::{make_charm_zip, zip_with};
+
+    const METADATA: &str = r#"
+name: foo
+summary: A foo charm
+description: Does foo things
+resources:
+  foo-image:
+    type: oci-image
+    upstream-source: foo/foo:latest
+"#;
+
+    const CHARMCRAFT: &str = r#"
+type: charm
+bases:
+  - build-on:
+      - name: ubuntu
+        channel: "20.04"
+    run-on:
+      - name: ubuntu
+        channel: "20.04"
+"#;
+
+    /// What charmcraft prints when uploading `foo`, with every resource at `resource_revision`
+    fn canned_upload(cmd: &str, args: &[String], resource_revision: u32) -> Vec<u8> {
+        match (cmd, args[0].as_str()) {
+            ("charmcraft", "resource-revisions") => {
+                format!(r#"[{{"revision": {}}}]"#, resource_revision).into_bytes()
+            }
+            ("charmcraft", "upload") => b"Revision 7 of 'foo' created\n".to_vec(),
+            _ => vec![],
+        }
+    }
+
+    fn write_charm(dir: &Path, metadata: &str) {
+        write(dir.join("metadata.yaml"), metadata).unwrap();
+        write(dir.join("charmcraft.yaml"), CHARMCRAFT).unwrap();
+    }
+
+    /// Writes a charm with `metadata` to a new directory, and loads it with a `MockRunner` that
+    /// answers with `respond`
+    fn mock_charm<F>(metadata: &str, respond: F) -> (TempDir, CharmSource, Arc<MockRunner>)
+    where
+        F: Fn(&str, &[String]) -> Result<Vec<u8>, JujuError> + Send + Sync + 'static,
+    {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), metadata);
+
+        let runner = MockRunner::new(respond);
+        let charm = CharmSource::load(dir.path())
+            .unwrap()
+            .with_runner(runner.clone());
+        (dir, charm, runner)
+    }
+
+    #[test]
+    fn test_upload_report() {
+        let (_dir, charm, runner) = mock_charm(METADATA, |cmd, args| {
+            let output = match (cmd, args[0].as_str()) {
+                ("git", _) => "0123abcd\n",
+                _ => return Ok(canned_upload(cmd, args, 3)),
+            };
+            Ok(output.as_bytes().to_vec())
+        });
+
+        let report = charm
+            .upload_charmhub(&HashMap::new(), &["edge".into()], false)
+            .unwrap();
+        let parsed: Value = from_str(&report.to_json().unwrap()).unwrap();
+
+        assert_eq!(parsed["charm"], json!("foo"));
+        assert_eq!(parsed["revision-url"], json!("foo-7"));
+        assert_eq!(parsed["resource-revisions"], json!({"foo-image": 3}));
+        assert_eq!(parsed["channels"], json!(["edge"]));
+        assert_eq!(parsed["git-commit"], json!("0123abcd"));
+        assert!(parsed["duration-secs"].is_f64());
+
+        let calls = runner.calls();
+        assert_eq!(calls[0][..2], ["charmcraft", "pack"]);
+        assert_eq!(calls[1][..3], ["docker", "image", "inspect"]);
+        assert_eq!(
+            calls[2],
+            [
+                "charmcraft",
+                "upload-resource",
+                "foo",
+                "foo-image",
+                "--image",
+                "foo/foo:latest"
+            ]
+        );
+        assert!(calls
+            .iter()
+            .any(|c| c[1] == "upload" && c.contains(&"--resource=foo-image:3".to_string())));
+    }
+
+    #[test]
+    fn test_build_all() {
+        let foo = TempDir::new().unwrap();
+        let bar = TempDir::new().unwrap();
+        write_charm(foo.path(), METADATA);
+        write_charm(bar.path(), &METADATA.replace("name: foo", "name: bar"));
+
+        let runner = MockRunner::new(|_, _| Ok(vec![]));
+        let sources: Vec<_> = [foo.path(), bar.path()]
+            .iter()
+            .map(|path| {
+                CharmSource::load(*path)
+                    .unwrap()
+                    .with_runner(runner.clone())
+            })
+            .collect();
+
+        let results = build_all(&sources, true, 2);
+        let names: Vec<_> = results
+            .into_iter()
+            .map(|paths| paths.unwrap()[0].file_name().unwrap().to_owned())
+            .collect();
+
+        assert_eq!(
+            names,
+            [
+                "foo_ubuntu-20.04-amd64.charm",
+                "bar_ubuntu-20.04-amd64.charm"
+            ]
+        );
+        assert_eq!(runner.calls().len(), 2);
+        assert!(runner
+            .calls()
+            .iter()
+            .all(|c| c[1] == "pack" && c.contains(&"--destructive-mode".to_string())));
+    }
+
+    #[test]
+    fn test_from_git() {
+        let root = TempDir::new().unwrap();
+        let work = root.path().join("work");
+        let bare = root.path().join("bare.git");
+        let git = |args: &[&str]| cmd::get_output("git", args).unwrap();
+
+        ex::fs::create_dir(&work).unwrap();
+        write_charm(&work, METADATA);
+        let work = work.to_string_lossy();
+        git(&["-C", &work, "init", "--quiet"]);
+        git(&["-C", &work, "checkout", "--quiet", "-b", "release"]);
+        git(&["-C", &work, "add", "."]);
+        git(&[
+            "-C",
+            &work,
+            "-c",
+            "user.name=Test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "--quiet",
+            "-m",
+            "Initial commit",
+        ]);
+        git(&["clone", "--quiet", "--bare", &work, &bare.to_string_lossy()]);
+
+        let url = format!("file://{}", bare.display());
+
+        let dest = root.path().join("checkout");
+        let charm = CharmSource::from_git(&url, Some("release"), &dest).unwrap();
+        assert_eq!(charm.metadata.name, "foo");
+        assert!(dest.join("metadata.yaml").exists());
+
+        let sha = String::from_utf8(git(&["-C", &work, "rev-parse", "HEAD"])).unwrap();
+        let pinned = root.path().join("pinned");
+        let charm = CharmSource::from_git(&url, Some(sha.trim()), &pinned).unwrap();
+        assert_eq!(charm.metadata.name, "foo");
+
+        let missing = root.path().join("missing");
+        assert!(CharmSource::from_git(&url, Some("no-such-branch"), &missing).is_err());
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn test_resource_revisions() {
+        let (_dir, charm, runner) = mock_charm(METADATA, |_, _| {
+            Ok(charmhub::tests::STATUS.as_bytes().to_vec())
+        });
+
+        let revisions = charm.resource_revisions("foo", &Channel::Stable).unwrap();
+        let expected: HashMap<_, _> = [("foo-image".to_string(), 5), ("bar-image".to_string(), 2)]
+            .iter()
+            .cloned()
+            .collect();
+        assert_eq!(revisions, expected);
+
+        assert!(charm
+            .resource_revisions("foo", &Channel::Edge)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            runner.calls()[0],
+            ["charmcraft", "status", "foo", "--format", "json"]
+        );
+    }
+
+    #[test]
+    fn test_find_artifact_newest() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        let charm = CharmSource::load(dir.path()).unwrap();
+
+        let out = TempDir::new().unwrap();
+        let now = SystemTime::now();
+        let candidates = [
+            ("foo_ubuntu-22.04-amd64.charm", 30),
+            ("foo_ubuntu-20.04-arm64.charm", 10),
+            ("foo_ubuntu-18.04-amd64.charm", 20),
+            ("foobar_ubuntu-20.04-amd64.charm", 0),
+        ];
+        for (name, age) in &candidates {
+            let file = File::create(out.path().join(name)).unwrap();
+            file.set_modified(now - Duration::from_secs(*age)).unwrap();
+        }
+
+        assert_eq!(
+            charm.find_artifact_in(out.path()).unwrap(),
+            out.path().join("foo_ubuntu-20.04-arm64.charm")
+        );
+
+        let empty = TempDir::new().unwrap();
+        assert!(matches!(
+            charm.find_artifact_in(empty.path()),
+            Err(JujuError::ArtifactNotFound(name)) if name == "foo"
+        ));
+    }
+
+    #[test]
+    fn test_read_file_dir() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        write(dir.path().join("dispatch"), "#!/bin/sh\n").unwrap();
+        let charm = CharmSource::load(dir.path()).unwrap();
+
+        assert_eq!(
+            charm.read_file(Path::new("dispatch")).unwrap(),
+            Some(b"#!/bin/sh\n".to_vec())
+        );
+        assert_eq!(charm.read_file(Path::new("metrics.yaml")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_file_zip() {
+        let dir = TempDir::new().unwrap();
+        let metadata: Metadata = from_slice(METADATA.as_bytes()).unwrap();
+        let path = dir.path().join("foo.charm");
+        write(&path, make_charm_zip(&metadata, None)).unwrap();
+        let charm = CharmSource::load(&path).unwrap();
+
+        let bytes = charm
+            .read_file(Path::new("metadata.yaml"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(from_slice::<Metadata>(&bytes).unwrap(), metadata);
+        assert_eq!(charm.read_file(Path::new("metrics.yaml")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        write(
+            dir.path().join("metrics.yaml"),
+            "metrics:\n  juju-units:\n  users:\n    type: gauge\n    description: Active users\n",
+        )
+        .unwrap();
+
+        let charm = CharmSource::load(dir.path()).unwrap();
+
+        assert_eq!(charm.metric_names(), ["juju-units", "users"]);
+        assert_eq!(
+            charm.metrics.unwrap().metrics["users"],
+            Some(Metric {
+                kind: MetricType::Gauge,
+                description: "Active users".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_upload_builds_image() {
+        let (dir, charm, runner) = mock_charm(
+            &format!("{}    build:\n      context: image\n", METADATA),
+            |cmd, args| Ok(canned_upload(cmd, args, 4)),
+        );
+        charm.upload_charmhub(&HashMap::new(), &[], false).unwrap();
+
+        let calls = runner.calls();
+        let build = calls.iter().find(|c| c[0] == "docker").unwrap();
+        let tag = &build[3];
+        assert_eq!(build[1..3], ["build", "-t"]);
+        assert!(tag.starts_with("foo-foo-image:"));
+        assert_eq!(build[5], dir.path().join("Dockerfile").to_string_lossy());
+        assert_eq!(build[6], dir.path().join("image").to_string_lossy());
+
+        let upload = calls.iter().find(|c| c[1] == "upload-resource").unwrap();
+        assert_eq!(upload[4], "--image");
+        assert_eq!(&upload[5], tag);
+    }
+
+    #[test]
+    fn test_one_line() {
+        let dir = TempDir::new().unwrap();
+        write_charm(
+            dir.path(),
+            &format!(
+                "{}provides:\n  website: {{interface: http}}\npeer:\n  replicas: {{interface: foo-peers}}\n",
+                METADATA
+            ),
+        );
+        let charm = CharmSource::load(dir.path()).unwrap();
+
+        assert_eq!(
+            charm.one_line(),
+            "foo — A foo charm (2 relations, 1 resources)"
+        );
+    }
+
+    #[test]
+    fn test_wait_for_revision() {
+        let polls = std::sync::atomic::AtomicUsize::new(0);
+        let (_dir, charm, mock) = mock_charm(METADATA, move |_, _| {
+            match polls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => Err(JujuError::SubcommandError(
+                    "charmcraft".into(),
+                    "not found".into(),
+                )),
+                1 => Ok(b"[]".to_vec()),
+                _ => Ok(br#"[{"revision": 4, "version": "4", "status": "approved"}]"#.to_vec()),
+            }
+        });
+
+        charm
+            .wait_for_revision_every("foo", 4, Duration::from_secs(5), Duration::from_millis(1))
+            .unwrap();
+        let calls = mock.calls();
+        assert_eq!(calls.len(), 3);
+        assert!(calls.iter().all(|c| c[1] == "revisions" && c[2] == "foo"));
+
+        assert!(matches!(
+            charm.wait_for_revision_every(
+                "foo",
+                9,
+                Duration::from_millis(5),
+                Duration::from_millis(1)
+            ),
+            Err(JujuError::Timeout(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_upload_revision() {
+        assert_eq!(
+            parse_upload_revision(b"Revision 7 of 'foo' created\n").unwrap(),
+            7
+        );
+        assert_eq!(
+            parse_upload_revision(b"Uploading...\nRevision 12 of 'foo' created\n").unwrap(),
+            12
+        );
+        assert!(parse_upload_revision(b"").is_err());
+        assert!(parse_upload_revision(b"Revision x of 'foo' created").is_err());
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let metadata: Metadata = serde_yaml::from_str(METADATA).unwrap();
+        let zip = make_charm_zip(&metadata, None);
+
+        let charm = CharmSource::from_reader(std::io::Cursor::new(zip)).unwrap();
+
+        assert_eq!(charm.metadata, metadata);
+        assert_eq!(charm.config, None);
+        assert!(charm
+            .read_file(Path::new("metadata.yaml"))
+            .unwrap()
+            .is_some());
+        assert_eq!(charm.read_file(Path::new("README.md")).unwrap(), None);
+
+        assert_eq!(charm.source, PathBuf::new());
+
+        let clone = charm.clone();
+        drop(charm);
+        assert!(clone
+            .read_file(Path::new("metadata.yaml"))
+            .unwrap()
+            .is_some());
+
+        let dir = TempDir::new().unwrap();
+        let extracted = clone.clone_to(&dir.path().join("foo")).unwrap();
+        assert_eq!(extracted.metadata, metadata);
+        assert!(dir.path().join("foo/metadata.yaml").is_file());
+    }
+
+    #[test]
+    fn test_mirror_images() {
+        let (_dir, charm, runner) = mock_charm(
+            &format!(
+                "{}  bar-image:\n    type: oci-image\n    description: Bar\n",
+                METADATA
+            ),
+            |_, _| Ok(vec![]),
+        );
+
+        let resources = [("bar-image".to_string(), "ghcr.io/acme/bar:1.0".to_string())].into();
+        let mirrored = charm
+            .mirror_images(&resources, "registry.internal:5000/")
+            .unwrap();
+
+        assert_eq!(
+            mirrored["foo-image"],
+            "registry.internal:5000/foo/foo:latest"
+        );
+        assert_eq!(mirrored["bar-image"], "registry.internal:5000/acme/bar:1.0");
+
+        let calls = runner.calls();
+        assert_eq!(calls.len(), 6);
+        assert!(calls.contains(&vec![
+            "docker".into(),
+            "tag".into(),
+            "ghcr.io/acme/bar:1.0".into(),
+            "registry.internal:5000/acme/bar:1.0".into(),
+        ]));
+        for image in &["foo/foo:latest", "ghcr.io/acme/bar:1.0"] {
+            assert!(calls.contains(&vec!["docker".into(), "pull".into(), image.to_string()]));
+        }
+        for image in mirrored.values() {
+            assert!(calls.contains(&vec!["docker".into(), "push".into(), image.clone()]));
+        }
+    }
+
+    #[test]
+    fn test_upload_charmhub_native() {
+        let (_dir, charm, runner) =
+            mock_charm(METADATA, |cmd, args| Ok(canned_upload(cmd, args, 4)));
+        let report = charm
+            .upload_charmhub(&HashMap::new(), &["edge".into()], false)
+            .unwrap();
+
+        assert_eq!(report.revision_url, "foo-7");
+        assert_eq!(report.resource_revisions["foo-image"], 4);
+
+        let calls = runner.calls();
+        // docker is only asked whether the image was uploaded before
+        assert!(calls.iter().all(|c| c[0] == "charmcraft"
+            || c[0] == "git"
+            || c[..3] == ["docker", "image", "inspect"]));
+        let subcommands: Vec<_> = calls
+            .iter()
+            .filter(|c| c[0] == "charmcraft")
+            .map(|c| c[1].as_str())
+            .collect();
+        assert_eq!(
+            subcommands,
+            ["pack", "upload-resource", "resource-revisions", "upload"]
+        );
+
+        let upload = calls.iter().find(|c| c[1] == "upload").unwrap();
+        assert!(upload.contains(&"--release=edge".to_string()));
+        assert!(upload.contains(&"--resource=foo-image:4".to_string()));
+    }
+
+    #[test]
+    fn test_embedded_revision() {
+        let dir = TempDir::new().unwrap();
+        let write_zip = |name: &str, files: &[(&str, &str)]| {
+            let path = dir.path().join(name);
+            write(&path, zip_with(files)).unwrap();
+            path
+        };
+
+        let manifest = write_zip(
+            "manifest.charm",
+            &[(
+                "manifest.yaml",
+                "charmcraft-version: 2.5.0\nrevision: 42\nbases: []\n",
+            )],
+        );
+        assert_eq!(CharmSource::embedded_revision(&manifest).unwrap(), Some(42));
+
+        let legacy = write_zip("legacy.charm", &[("revision", "7\n")]);
+        assert_eq!(CharmSource::embedded_revision(&legacy).unwrap(), Some(7));
+
+        let none = write_zip(
+            "none.charm",
+            &[("manifest.yaml", "charmcraft-version: 2.5.0\n")],
+        );
+        assert_eq!(CharmSource::embedded_revision(&none).unwrap(), None);
+    }
+
+    #[test]
+    fn test_manifest() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        let charm = CharmSource::load(dir.path()).unwrap();
+        assert_eq!(charm.manifest().unwrap(), None);
+
+        write(
+            dir.path().join("manifest.yaml"),
+            r#"
+analysis:
+  attributes:
+  - name: language
+    result: python
+  - name: framework
+    result: operator
+bases:
+- architectures:
+  - amd64
+  channel: '20.04'
+  name: ubuntu
+charmcraft-started-at: '2021-09-01T10:00:00.000000Z'
+charmcraft-version: 1.2.1
+"#,
+        )
+        .unwrap();
+
+        let manifest = charm.manifest().unwrap().unwrap();
+        assert_eq!(manifest.charmcraft_version, "1.2.1");
+        assert_eq!(
+            manifest.bases,
+            [ManifestBase {
+                name: "ubuntu".into(),
+                channel: "20.04".into(),
+                architectures: vec!["amd64".into()],
+            }]
+        );
+        assert_eq!(manifest.analysis.attributes[1].result, "operator");
+    }
+
+    #[test]
+    fn test_private_image_registries() {
+        let dir = TempDir::new().unwrap();
+        write_charm(
+            dir.path(),
+            &format!(
+                "{}  bar-image:\n    type: oci-image\n    upstream-source: registry.acme.internal:5000/bar:1\n  baz-image:\n    type: oci-image\n    upstream-source: ghcr.io/acme/baz:1\n",
+                METADATA
+            ),
+        );
+        let charm = CharmSource::load(dir.path()).unwrap();
+
+        assert_eq!(
+            charm.private_image_registries(),
+            ["registry.acme.internal:5000"]
+        );
+        assert_eq!(
+            charm.private_image_registries_excluding(&["registry.acme.internal:5000"]),
+            ["docker.io", "ghcr.io"]
+        );
+    }
+
+    #[test]
+    fn test_status_cached() {
+        let (_dir, charm, runner) = mock_charm(METADATA, |_, _| {
+            Ok(charmhub::tests::STATUS.as_bytes().to_vec())
+        });
+
+        let first = charm.status_cached("foo").unwrap();
+        let second = charm.status_cached("foo").unwrap();
+        assert_eq!(first, second);
+        assert_eq!(runner.calls().len(), 1);
+
+        charm.clear_status_cache();
+        charm.status_cached("foo").unwrap();
+        assert_eq!(runner.calls().len(), 2);
+    }
+
+    #[test]
+    fn test_is_buildable() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        write(dir.path().join("src/charm.py"), "").unwrap();
+
+        let charm = CharmSource::load(dir.path()).unwrap();
+        charm.is_buildable().unwrap();
+
+        std::fs::remove_file(dir.path().join("metadata.yaml")).unwrap();
+        match charm.is_buildable() {
+            Err(JujuError::NotBuildable(_, reason)) => {
+                assert_eq!(reason, "metadata.yaml is missing")
+            }
+            other => panic!("Expected NotBuildable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_upload_progress() {
+        let (_dir, charm, _) = mock_charm(METADATA, |cmd, args| {
+            let output = match (cmd, args[0].as_str()) {
+                ("charmcraft", "upload-resource") => {
+                    "Uploading... 10%\rUploading... 10%\rUploading... 55.5%\rgarbled\nUploading... 100%\nRevision 4 created\n"
+                }
+                _ => return Ok(canned_upload(cmd, args, 4)),
+            };
+            Ok(output.as_bytes().to_vec())
+        });
+
+        let progress = std::sync::Mutex::new(Vec::new());
+        charm
+            .upload_charmhub_with_progress(&HashMap::new(), &[], false, &|p| {
+                progress.lock().unwrap().push(p)
+            })
+            .unwrap();
+
+        let progress = progress.into_inner().unwrap();
+        assert!(progress.iter().all(|p| p.resource == "foo-image"));
+        assert_eq!(
+            progress.iter().map(|p| p.percent).collect::<Vec<_>>(),
+            [10, 55, 100]
+        );
+    }
+
+    #[test]
+    fn test_load_lenient() {
+        let dir = TempDir::new().unwrap();
+        write_charm(
+            dir.path(),
+            &format!("{}display-name: Foo\nflavour: vanilla\n", METADATA),
+        );
+
+        match CharmSource::load(dir.path()) {
+            Err(JujuError::UnknownField(file, field)) => {
+                assert_eq!(file, "metadata.yaml");
+                assert_eq!(field, "flavour");
+            }
+            other => panic!("Expected UnknownField, got {:?}", other),
+        }
+
+        std::fs::remove_file(dir.path().join("charmcraft.yaml")).unwrap();
+        assert!(CharmSource::load_with(dir.path(), &LoadOptions { strict: false }).is_ok());
+        assert!(matches!(
+            CharmSource::load(dir.path()),
+            Err(JujuError::UnknownField(..))
+        ));
+
+        write(dir.path().join("metadata.yaml"), METADATA).unwrap();
+        assert!(matches!(
+            CharmSource::load(dir.path()),
+            Err(JujuError::ExIOError(_))
+        ));
+
+        let charm = CharmSource::load_lenient(dir.path()).unwrap();
+        assert_eq!(charm.metadata.name, "foo");
+        assert!(charm.charmcraft.bases.is_empty());
+        assert!(matches!(
+            charm.artifact_path(),
+            Err(JujuError::ArtifactNotFound(name)) if name == "foo"
+        ));
+    }
+
+    #[test]
+    fn test_load_ignores_unknown_fields() {
+        let dir = TempDir::new().unwrap();
+        write_charm(
+            dir.path(),
+            &format!(
+                "{}links:\n  source: https://example.com/foo\nrequires:\n  db:\n    interface: mysql\n    description: The database\npeers:\n  cluster:\n    interface: foo-cluster\n",
+                METADATA
+            ),
+        );
+
+        let charm = CharmSource::load_lenient(dir.path()).unwrap();
+        assert_eq!(charm.metadata.requires["db"].interface, "mysql");
+        assert_eq!(charm.metadata.peer["cluster"].interface, "foo-cluster");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract() {
+        use std::io::{Cursor, Write};
+        use std::os::unix::fs::PermissionsExt;
+        use zip::write::FileOptions;
+        use zip::ZipWriter;
+
+        let dir = TempDir::new().unwrap();
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let files = [
+            ("metadata.yaml", METADATA, 0o644),
+            ("dispatch", "#!/bin/sh\n", 0o755),
+            ("src/charm.py", "", 0o644),
+        ];
+        for (name, contents, mode) in &files {
+            zip.start_file(*name, FileOptions::default().unix_permissions(*mode))
+                .unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        let charm = dir.path().join("foo.charm");
+        write(&charm, zip.finish().unwrap().into_inner()).unwrap();
+
+        let dest = dir.path().join("foo");
+        CharmSource::extract(&charm, &dest).unwrap();
+
+        let mode = |name| {
+            std::fs::metadata(dest.join(name))
+                .unwrap()
+                .permissions()
+                .mode()
+        };
+        assert_eq!(mode("dispatch") & 0o777, 0o755);
+        assert_eq!(mode("metadata.yaml") & 0o777, 0o644);
+        assert_eq!(
+            std::fs::read_to_string(dest.join("metadata.yaml")).unwrap(),
+            METADATA
+        );
+        assert!(dest.join("src/charm.py").is_file());
+    }
+
+    #[test]
+    fn test_upload_arch_scoped_images() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        write(
+            dir.path().join("charmcraft.yaml"),
+            format!("{}architectures: [aarch64]\n", CHARMCRAFT),
+        )
+        .unwrap();
+
+        let runner = MockRunner::new(|cmd, args| Ok(canned_upload(cmd, args, 4)));
+        let charm = CharmSource::load(dir.path())
+            .unwrap()
+            .with_runner(runner.clone());
+        charm.upload_charmhub(&HashMap::new(), &[], false).unwrap();
+
+        let calls = runner.calls();
+        let scoped = calls
+            .iter()
+            .find(|c| c[1] == "set-resource-architectures")
+            .unwrap();
+        assert_eq!(scoped[2..], ["foo", "foo-image", "--revision=4", "arm64"]);
+    }
+
+    #[test]
+    fn test_promote_refuses_downgrade() {
+        let (_dir, charm, runner) = mock_charm(METADATA, |_, _| {
+            Ok(charmhub::tests::STATUS.as_bytes().to_vec())
+        });
+
+        // Stable has revision 3, and beta has the newer revision 4
+        assert!(matches!(
+            charm.promote("foo", &Channel::Stable, &Channel::Beta, false),
+            Err(JujuError::WouldDowngrade(_, channel, 4, 3)) if channel == "beta"
+        ));
+        assert!(runner.calls().iter().all(|c| c[1] != "release"));
+
+        assert_eq!(
+            charm
+                .promote("foo", &Channel::Stable, &Channel::Beta, true)
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            charm
+                .promote("foo", &Channel::Beta, &Channel::Stable, false)
+                .unwrap(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_validate_dispatch() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        let charm = CharmSource::load(dir.path()).unwrap();
+
+        // Not an operator framework charm, so dispatch isn't needed
+        charm.validate().unwrap();
+
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        write(dir.path().join("src/charm.py"), "").unwrap();
+        assert!(matches!(
+            charm.validate(),
+            Err(JujuError::MissingDispatch(name)) if name == "foo"
+        ));
+
+        let dispatch = dir.path().join("dispatch");
+        write(&dispatch, "#!/bin/sh\n").unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&dispatch, std::fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(matches!(
+                charm.validate(),
+                Err(JujuError::MissingDispatch(_))
+            ));
+            std::fs::set_permissions(&dispatch, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        charm.validate().unwrap();
+    }
+
+    #[test]
+    fn test_resources_interpolated() {
+        let dir = TempDir::new().unwrap();
+        write_charm(
+            dir.path(),
+            r#"
+name: foo
+summary: A foo charm
+description: Does foo things
+resources:
+  foo-image:
+    type: oci-image
+    upstream-source: ${REGISTRY}/foo:${TAG}
+  bar-image:
+    type: oci-image
+    upstream-source: bar/bar:latest
+"#,
+        );
+        let charm = CharmSource::load(dir.path()).unwrap();
+
+        let vars: HashMap<_, _> = [
+            ("REGISTRY".to_string(), "registry.example.com".to_string()),
+            ("TAG".to_string(), "1.2".to_string()),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let resources = charm.resources_with_vars(&HashMap::new(), &vars).unwrap();
+        assert_eq!(resources["foo-image"], "registry.example.com/foo:1.2");
+        assert_eq!(resources["bar-image"], "bar/bar:latest");
+
+        let mut missing = vars.clone();
+        missing.remove("TAG");
+        assert!(matches!(
+            charm.resources_with_vars(&HashMap::new(), &missing),
+            Err(JujuError::UndefinedVariable(var)) if var == "TAG"
+        ));
+
+        let literal = charm.resources_with_defaults(&HashMap::new()).unwrap();
+        assert_eq!(literal["foo-image"], "${REGISTRY}/foo:${TAG}");
+    }
+
+    #[test]
+    fn test_clone_to() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.path().join("foo");
+        std::fs::create_dir_all(source.join("src")).unwrap();
+        std::fs::create_dir_all(source.join("build")).unwrap();
+        std::fs::create_dir_all(source.join("src/foo/build")).unwrap();
+        write_charm(&source, METADATA);
+        write(source.join("src/charm.py"), "").unwrap();
+        write(source.join("src/foo/build/__init__.py"), "").unwrap();
+        write(source.join("foo_ubuntu-20.04-amd64.charm"), "").unwrap();
+
+        let charm = CharmSource::load(&source).unwrap();
+        let dest = dir.path().join("copy");
+        let cloned = charm.clone_to(&dest).unwrap();
+
+        assert_eq!(cloned.metadata, charm.metadata);
+        assert_eq!(cloned.charmcraft, charm.charmcraft);
+        assert!(dest.join("src/charm.py").is_file());
+        assert!(dest.join("src/foo/build/__init__.py").is_file());
+        assert!(!dest.join("build").exists());
+        assert!(!dest.join("foo_ubuntu-20.04-amd64.charm").exists());
+    }
+
+    #[test]
+    fn test_upload_multiple_bases() {
+        let dir = TempDir::new().unwrap();
+        write(dir.path().join("metadata.yaml"), METADATA).unwrap();
+        write(
+            dir.path().join("charmcraft.yaml"),
+            r#"
+type: charm
+bases:
+  - build-on: [{name: ubuntu, channel: "20.04"}]
+    run-on: [{name: ubuntu, channel: "20.04"}]
+  - build-on: [{name: ubuntu, channel: "22.04"}]
+    run-on: [{name: ubuntu, channel: "22.04"}]
+"#,
+        )
+        .unwrap();
+
+        let runner = MockRunner::new(|cmd, args| {
+            let output = match (cmd, args[0].as_str()) {
+                ("charmcraft", "resource-revisions") => r#"[{"revision": 3}]"#,
+                ("charmcraft", "upload") if args[2].contains("20.04") => {
+                    "Revision 7 of 'foo' created\n"
+                }
+                ("charmcraft", "upload") => "Revision 8 of 'foo' created\n",
+                _ => "",
+            };
+            Ok(output.as_bytes().to_vec())
+        });
+
+        let charm = CharmSource::load(dir.path())
+            .unwrap()
+            .with_runner(runner.clone());
+
+        let report = charm
+            .upload_charmhub(&HashMap::new(), &["edge".into()], false)
+            .unwrap();
+
+        assert_eq!(report.base_revisions["ubuntu-20.04-amd64"], "foo-7");
+        assert_eq!(report.base_revisions["ubuntu-22.04-amd64"], "foo-8");
+
+        let calls = runner.calls();
+        let uploads: Vec<_> = calls.iter().filter(|c| c[1] == "upload").collect();
+        assert_eq!(uploads.len(), 2);
+        assert!(uploads.iter().all(|c| c.len() == 4));
+
+        let releases: Vec<_> = calls.iter().filter(|c| c[1] == "release").collect();
+        let release = |revision: &str| {
+            vec![
+                "charmcraft".to_string(),
+                "release".into(),
+                "foo".into(),
+                format!("--revision={}", revision),
+                "--channel=edge".into(),
+                "--resource=foo-image:3".into(),
+            ]
+        };
+        assert_eq!(releases, [&release("7"), &release("8")]);
+    }
+
+    #[test]
+    fn test_load_metadata_only() {
+        let dir = TempDir::new().unwrap();
+        write(dir.path().join("metadata.yaml"), METADATA).unwrap();
+        write(dir.path().join("config.yaml"), "not: [valid").unwrap();
+
+        let metadata = CharmSource::load_metadata_only(dir.path()).unwrap();
+        assert_eq!(metadata.name, "foo");
+        assert_eq!(metadata.summary, "A foo charm");
+
+        let unified = TempDir::new().unwrap();
+        write(
+            unified.path().join("charmcraft.yaml"),
+            format!("{}{}", METADATA, CHARMCRAFT),
+        )
+        .unwrap();
+        assert_eq!(
+            CharmSource::load_metadata_only(unified.path())
+                .unwrap()
+                .name,
+            "foo"
+        );
+
+        let empty = TempDir::new().unwrap();
+        assert!(matches!(
+            CharmSource::load_metadata_only(empty.path()),
+            Err(JujuError::IOError(err)) if err.kind() == ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_resource_drift() {
+        let dir = TempDir::new().unwrap();
+        write_charm(
+            dir.path(),
+            r#"
+name: foo
+summary: A foo charm
+description: Does foo things
+resources:
+  foo-image:
+    type: oci-image
+    upstream-source: foo/foo:2
+  bar-image:
+    type: oci-image
+    upstream-source: bar/bar:1
+  baz-image:
+    type: oci-image
+    upstream-source: baz/baz:1
+  qux-image:
+    type: oci-image
+    upstream-source: qux/qux:1
+"#,
+        );
+
+        let status = r#"[{"track": "latest", "mappings": [{"releases": [{
+            "status": "open",
+            "channel": "latest/stable",
+            "revision": 3,
+            "resources": [
+                {"name": "foo-image", "revision": 5, "origin": "foo/foo:1"},
+                {"name": "bar-image", "revision": 2, "origin": "bar/bar:1"},
+                {"name": "baz-image", "revision": 1}
+            ]
+        }]}]}]"#;
+        let runner = MockRunner::new(move |_, _| Ok(status.as_bytes().to_vec()));
+        let charm = CharmSource::load(dir.path()).unwrap().with_runner(runner);
+
+        assert_eq!(
+            charm.resource_drift("foo", &Channel::Stable).unwrap(),
+            [
+                ResourceDrift {
+                    resource: "foo-image".into(),
+                    declared: Some("foo/foo:2".into()),
+                    released_revision: Some(5),
+                    released_origin: Some("foo/foo:1".into()),
+                },
+                ResourceDrift {
+                    resource: "qux-image".into(),
+                    declared: Some("qux/qux:1".into()),
+                    released_revision: None,
+                    released_origin: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_readme() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        let charm = CharmSource::load(dir.path()).unwrap();
+        assert_eq!(charm.readme().unwrap(), None);
+
+        write(dir.path().join("README.md"), "# foo\n").unwrap();
+        assert_eq!(charm.readme().unwrap().as_deref(), Some("# foo\n"));
+    }
+
+    #[test]
+    fn test_upload_archive_file_resource() {
+        let dir = TempDir::new().unwrap();
+        write_charm(
+            dir.path(),
+            "name: foo\nsummary: foo\ndescription: foo\nresources:\n  data:\n    type: file\n    filename: data.tar\n",
+        );
+
+        let archive = dir.path().join("other.charm");
+        write(&archive, zip_with(&[("files/data.tar", b"payload")])).unwrap();
+
+        let runner = MockRunner::new(|cmd, args| {
+            let output = match (cmd, args[0].as_str()) {
+                ("charmcraft", "upload-resource") => {
+                    assert_eq!(args[3], "--filepath");
+                    assert_eq!(std::fs::read(&args[4]).unwrap(), b"payload");
+                    ""
+                }
+                _ => return Ok(canned_upload(cmd, args, 2)),
+            };
+            Ok(output.as_bytes().to_vec())
+        });
+        let charm = CharmSource::load(dir.path())
+            .unwrap()
+            .with_runner(runner.clone());
+
+        let resources = [(
+            "data".to_string(),
+            format!("archive:{}!files/data.tar", archive.display()),
+        )]
+        .iter()
+        .cloned()
+        .collect();
+        let report = charm.upload_charmhub(&resources, &[], false).unwrap();
+        assert_eq!(report.resource_revisions["data"], 2);
+
+        let missing = [(
+            "data".to_string(),
+            format!("archive:{}!files/missing.tar", archive.display()),
+        )]
+        .iter()
+        .cloned()
+        .collect();
+        assert!(matches!(
+            charm.upload_charmhub(&missing, &[], false),
+            Err(JujuError::ArchiveEntryNotFound(entry, _)) if entry == "files/missing.tar"
+        ));
+    }
+
+    #[test]
+    fn test_unbacked_containers() {
+        let dir = TempDir::new().unwrap();
+        write_charm(
+            dir.path(),
+            r#"
+name: foo
+summary: A foo charm
+description: Does foo things
+containers:
+  foo:
+    resource: foo-image
+  bar:
+    resource: bar-image
+resources:
+  foo-image:
+    type: oci-image
+    upstream-source: foo/foo:latest
+  bar-image:
+    type: oci-image
+"#,
+        );
+        let charm = CharmSource::load(dir.path()).unwrap();
+
+        assert_eq!(charm.unbacked_containers(&HashMap::new()), ["bar"]);
+
+        let overrides = [("bar-image".to_string(), "bar/bar:1".to_string())]
+            .iter()
+            .cloned()
+            .collect();
+        assert!(charm.unbacked_containers(&overrides).is_empty());
+    }
+
+    #[test]
+    fn test_empty_config() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        write(
+            dir.path().join("config.yaml"),
+            "\n  \n# TODO: add options\n",
+        )
+        .unwrap();
+
+        let charm = CharmSource::load(dir.path()).unwrap();
+        assert!(charm.config.unwrap().options.is_empty());
+
+        write(dir.path().join("config.yaml"), "options: [").unwrap();
+        assert!(CharmSource::load(dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_libraries() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        let charm = CharmSource::load(dir.path()).unwrap();
+        assert!(charm.libraries().unwrap().is_empty());
+
+        let lib = dir.path().join("lib/charms/traefik_k8s/v1");
+        std::fs::create_dir_all(&lib).unwrap();
+        write(
+            lib.join("ingress.py"),
+            "LIBID = \"abc123\"\nLIBAPI = 1\nLIBPATCH = 4\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            charm.libraries().unwrap(),
+            [CharmLibrary {
+                charm: "traefik_k8s".into(),
+                name: "ingress".into(),
+                id: Some("abc123".into()),
+                api: 1,
+                patch: 4,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_upload_skips_uploaded_image() {
+        let (_dir, charm, runner) = mock_charm(METADATA, |cmd, args| {
+            let output = match (cmd, args[0].as_str()) {
+                ("docker", "image") => "[\"foo/foo@sha256:abc\"]\n",
+                ("charmcraft", "resource-revisions") => {
+                    r#"[{"revision": 2, "digest": "sha256:old"}, {"revision": 3, "digest": "sha256:abc"}]"#
+                }
+                ("charmcraft", "upload") => "Revision 7 of 'foo' created\n",
+                _ => "",
+            };
+            Ok(output.as_bytes().to_vec())
+        });
+        let report = charm
+            .upload_charmhub(&HashMap::new(), &["edge".into()], false)
+            .unwrap();
+
+        assert_eq!(report.resource_revisions["foo-image"], 3);
+
+        let calls = runner.calls();
+        assert!(calls.iter().all(|c| c[1] != "upload-resource"));
+        assert!(calls
+            .iter()
+            .any(|c| c[1] == "upload" && c.contains(&"--resource=foo-image:3".to_string())));
+    }
+
+    #[test]
+    fn test_upload_unlisted_resource_revisions() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+
+        // Listing fails until the first revision has been uploaded
+        let uploaded = std::sync::atomic::AtomicBool::new(false);
+        let runner = MockRunner::new(move |cmd, args| {
+            let output = match (cmd, args[0].as_str()) {
+                ("docker", "image") => "[\"foo/foo@sha256:abc\"]\n",
+                ("charmcraft", "resource-revisions")
+                    if !uploaded.load(std::sync::atomic::Ordering::SeqCst) =>
+                {
+                    return Err(JujuError::SubcommandError(
+                        "charmcraft".into(),
+                        "Resource not found".into(),
+                    ))
+                }
+                ("charmcraft", "resource-revisions") => r#"[{"revision": 1}]"#,
+                ("charmcraft", "upload-resource") => {
+                    uploaded.store(true, std::sync::atomic::Ordering::SeqCst);
+                    ""
+                }
+                ("charmcraft", "upload") => "Revision 7 of 'foo' created\n",
+                _ => "",
+            };
+            Ok(output.as_bytes().to_vec())
+        });
+
+        let charm = CharmSource::load(dir.path())
+            .unwrap()
+            .with_runner(runner.clone());
+        let report = charm
+            .upload_charmhub(&HashMap::new(), &["edge".into()], false)
+            .unwrap();
+
+        assert_eq!(report.resource_revisions["foo-image"], 1);
+        assert!(runner.calls().iter().any(|c| c[1] == "upload-resource"));
+    }
+
+    #[test]
+    fn test_upload_temp_dir() {
+        let dir = TempDir::new().unwrap();
+        write_charm(
+            dir.path(),
+            "name: foo\nsummary: foo\ndescription: foo\nresources:\n  data:\n    type: file\n    filename: data.tar\n",
+        );
+
+        let archive = dir.path().join("other.charm");
+        write(&archive, zip_with(&[("data.tar", b"payload")])).unwrap();
+
+        let scratch = TempDir::new().unwrap();
+        let scratch_path = scratch.path().to_path_buf();
+        let runner = MockRunner::new(move |cmd, args| {
+            let output = match (cmd, args[0].as_str()) {
+                ("charmcraft", "upload-resource") => {
+                    assert!(Path::new(&args[4]).starts_with(&scratch_path));
+                    ""
+                }
+                _ => return Ok(canned_upload(cmd, args, 2)),
+            };
+            Ok(output.as_bytes().to_vec())
+        });
+        let charm = CharmSource::load(dir.path())
+            .unwrap()
+            .with_temp_dir(scratch.path())
+            .with_runner(runner.clone());
+
+        let resources = [(
+            "data".to_string(),
+            format!("archive:{}!data.tar", archive.display()),
+        )]
+        .iter()
+        .cloned()
+        .collect();
+        charm.upload_charmhub(&resources, &[], false).unwrap();
+
+        assert!(runner.calls().iter().any(|c| c[1] == "upload-resource"));
+    }
+
+    #[test]
+    fn test_mistyped_config_default() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        write(
+            dir.path().join("config.yaml"),
+            "options:\n  port: {type: int, description: Port, default: \"80\"}\n",
+        )
+        .unwrap();
+
+        assert!(matches!(
+            CharmSource::load(dir.path()),
+            Err(JujuError::InvalidDefault(name, _)) if name == "port"
+        ));
+
+        write(
+            dir.path().join("config.yaml"),
+            "options:\n  port: {type: int, description: Port, default: 80}\n",
+        )
+        .unwrap();
+        CharmSource::load(dir.path()).unwrap().validate().unwrap();
+    }
+
+    #[test]
+    fn test_validate_containers() {
+        let dir = TempDir::new().unwrap();
+        let container = |spec: &str| {
+            write_charm(
+                dir.path(),
+                &format!("{}containers:\n  foo:\n{}", METADATA.trim_start(), spec),
+            );
+            CharmSource::load(dir.path()).unwrap().validate()
+        };
+
+        container("    resource: foo-image\n").unwrap();
+        container("    bases: [{name: ubuntu, channel: '22.04', architectures: [amd64]}]\n")
+            .unwrap();
+
+        assert!(matches!(
+            container("    resource: foo-image\n    bases: [{name: ubuntu, channel: '22.04', architectures: [amd64]}]\n"),
+            Err(JujuError::InvalidContainer(name, reason)) if name == "foo" && reason.contains("both")
+        ));
+        assert!(matches!(
+            container("    mounts: []\n"),
+            Err(JujuError::InvalidContainer(name, reason)) if name == "foo" && reason.contains("neither")
+        ));
+    }
+
+    #[test]
+    fn test_validate_relation_scope() {
+        let dir = TempDir::new().unwrap();
+        let relation = |subordinate: bool| {
+            write_charm(
+                dir.path(),
+                &format!(
+                    "{}subordinate: {}\nrequires:\n  juju-info:\n    interface: juju-info\n    scope: container\n",
+                    METADATA.trim_start(),
+                    subordinate
+                ),
+            );
+            CharmSource::load(dir.path()).unwrap().validate()
+        };
+
+        relation(true).unwrap();
+        assert!(matches!(
+            relation(false),
+            Err(JujuError::InvalidRelationScope(charm, name)) if charm == "foo" && name == "juju-info"
+        ));
+
+        write_charm(
+            dir.path(),
+            &format!(
+                "{}provides:\n  nrpe-external-master:\n    interface: nrpe-external-master\n    scope: container\n",
+                METADATA.trim_start()
+            ),
+        );
+        CharmSource::load(dir.path()).unwrap().validate().unwrap();
+    }
+
+    #[test]
+    fn test_next_revision() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        let charm = CharmSource::load(dir.path()).unwrap();
+
+        let charm = charm.with_runner(MockRunner::new(|_, _| {
+            Ok(charmhub::tests::STATUS.as_bytes().to_vec())
+        }));
+        assert_eq!(charm.next_revision("foo").unwrap(), 5);
+
+        let charm = charm.with_runner(MockRunner::new(|_, _| Ok(b"[]".to_vec())));
+        assert_eq!(charm.next_revision("foo").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_static_analysis_offline() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        write(
+            dir.path().join("config.yaml"),
+            "options:\n  port: {type: int, description: Port, default: 80}\n",
+        )
+        .unwrap();
+
+        let charm = CharmSource::load(dir.path())
+            .unwrap()
+            .with_runner(MockRunner::new(|cmd, args| {
+                panic!("Ran `{} {}` during static analysis", cmd, args.join(" "))
+            }));
+
+        charm.validate().unwrap();
+        // Not buildable, since there is no entry point
+        charm.is_buildable().unwrap_err();
+        assert!(charm.unbacked_containers(&HashMap::new()).is_empty());
+        assert!(charm.libraries().unwrap().is_empty());
+        charm.resources_with_defaults(&HashMap::new()).unwrap();
+
+        let bundle: crate::bundle::Bundle =
+            serde_yaml::from_str("bundle: kubernetes\napplications:\n  foo: {source: ./foo}\n")
+                .unwrap();
+        assert!(bundle.diff(&bundle).is_empty());
+        let sources = [("foo".to_string(), charm)].iter().cloned().collect();
+        bundle.plan(&sources).unwrap();
+    }
+
+    #[test]
+    fn test_validate_bundle_options() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        write(
+            dir.path().join("config.yaml"),
+            "options:\n  port: {type: int, description: Port, default: 80}\n",
+        )
+        .unwrap();
+        let charm = CharmSource::load(dir.path()).unwrap();
+
+        let options = |pairs: &[(&str, serde_yaml::Value)]| -> HashMap<_, _> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect()
+        };
+
+        charm
+            .validate_bundle_options(&options(&[("port", 8080.into())]))
+            .unwrap();
+
+        let errors = charm
+            .validate_bundle_options(&options(&[
+                ("port", "eighty".into()),
+                ("colour", "blue".into()),
+            ]))
+            .unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(&errors[0], JujuError::UnknownConfigOption(name) if name == "colour"));
+        assert!(matches!(&errors[1], JujuError::InvalidConfigValue(name, _) if name == "port"));
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        let charm = CharmSource::load(dir.path()).unwrap();
+
+        let sha256 = charm.content_hash(Digest::Sha256).unwrap();
+        let sha512 = charm.content_hash(Digest::Sha512).unwrap();
+        assert!(sha256.starts_with("sha256:") && sha256.len() == 7 + 64);
+        assert!(sha512.starts_with("sha512:") && sha512.len() == 7 + 128);
+
+        // Stable, and unaffected by build artifacts
+        write(dir.path().join("foo_ubuntu-20.04-amd64.charm"), "").unwrap();
+        assert_eq!(charm.content_hash(Digest::Sha256).unwrap(), sha256);
+        assert_eq!(charm.content_hash(Digest::default()).unwrap(), sha256);
+
+        write(dir.path().join("README.md"), "# foo\n").unwrap();
+        assert_ne!(charm.content_hash(Digest::Sha256).unwrap(), sha256);
+        assert_ne!(charm.content_hash(Digest::Sha512).unwrap(), sha512);
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        let charm = CharmSource::load(dir.path()).unwrap();
+
+        let artifact = dir.path().join("foo_ubuntu-20.04-amd64.charm");
+        write(
+            &artifact,
+            zip_with(&[("metadata.yaml", METADATA), ("charmcraft.yaml", CHARMCRAFT)]),
+        )
+        .unwrap();
+        assert!(!charm.is_stale(&artifact).unwrap());
+
+        write(dir.path().join("metadata.yaml"), format!("{}\n", METADATA)).unwrap();
+        assert!(charm.is_stale(&artifact).unwrap());
+    }
+
+    #[test]
+    fn test_resource_revisions_format_fallback() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+
+        // Newer charmcraft, with JSON output
+        let json_runner =
+            MockRunner::new(|_, _| Ok(br#"[{"revision": 5}, {"revision": 4}]"#.to_vec()));
+        let charm = CharmSource::load(dir.path())
+            .unwrap()
+            .with_runner(json_runner.clone());
+        let revisions = charm.uploaded_resource_revisions("foo-image").unwrap();
+        assert_eq!(ResourceRevision::latest(&revisions), Some(5));
+        assert_eq!(json_runner.calls().len(), 1);
+
+        // Older charmcraft, which only prints a table
+        let text_runner = MockRunner::new(|cmd, args| {
+            if args.iter().any(|a| a == "--format") {
+                return Err(JujuError::SubcommandError(
+                    format!("{} {}", cmd, args.join(" ")),
+                    "error: unrecognized arguments: --format json\n".into(),
+                ));
+            }
+            Ok(b"Revision    Created at    Size\n5           2021-10-02    1.2MB\n4           2021-10-01    1.2MB\n".to_vec())
+        });
+        let charm = CharmSource::load(dir.path())
+            .unwrap()
+            .with_runner(text_runner.clone());
+        let revisions = charm.uploaded_resource_revisions("foo-image").unwrap();
+        assert_eq!(ResourceRevision::latest(&revisions), Some(5));
+        assert_eq!(
+            text_runner.calls(),
+            [
+                vec![
+                    "charmcraft",
+                    "resource-revisions",
+                    "foo",
+                    "foo-image",
+                    "--format",
+                    "json"
+                ],
+                vec!["charmcraft", "resource-revisions", "foo", "foo-image"],
+            ]
+        );
+
+        // A known old version skips straight to the table
+        let text_runner = MockRunner::new(|_, _| Ok(b"Revision\n3\n".to_vec()));
+        let charm = CharmSource::load(dir.path())
+            .unwrap()
+            .with_charmcraft_version(Version::new(1, 2, 1))
+            .with_runner(text_runner.clone());
+        let revisions = charm.uploaded_resource_revisions("foo-image").unwrap();
+        assert_eq!(ResourceRevision::latest(&revisions), Some(3));
+        assert_eq!(
+            text_runner.calls(),
+            [vec!["charmcraft", "resource-revisions", "foo", "foo-image"]]
+        );
+    }
+
+    #[test]
+    fn test_open() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+
+        let charm = CharmSource::open(dir.path()).unwrap();
+        assert_eq!(charm.metadata.name, "foo");
+
+        let url = CharmURL::from_path(dir.path());
+        let charm = CharmSource::open(url).unwrap();
+        assert_eq!(charm.metadata.name, "foo");
+        assert_eq!(charm.source, dir.path());
+    }
+
+    #[cfg(not(feature = "download"))]
+    #[test]
+    fn test_open_remote() {
+        let url = CharmURL::parse("ch:foo").unwrap();
+
+        assert!(matches!(
+            CharmSource::open(url),
+            Err(JujuError::RemoteCharm(_))
+        ));
+    }
+
+    #[cfg(feature = "download")]
+    #[test]
+    fn test_open_remote() {
+        let runner = MockRunner::new(|_, args| {
+            let metadata: Metadata = serde_yaml::from_str(METADATA).unwrap();
+            write(&args[3], make_charm_zip(&metadata, None)).unwrap();
+            Ok(vec![])
+        });
+        let url = CharmURL::parse("ch:foo").unwrap();
+
+        let charm = CharmSource::open_with_runner(url, runner.clone()).unwrap();
+        assert_eq!(charm.metadata.name, "foo");
+
+        let calls = runner.calls();
+        assert_eq!(calls[0][..4], ["juju", "download", "ch:foo", "--filepath"]);
+        assert_eq!(Path::new(&calls[0][4]), charm.source);
+
+        let source = charm.source.clone();
+        drop(charm);
+        assert!(!source.exists());
+    }
+
+    #[test]
+    fn test_check_images() {
+        let (_dir, charm, runner) = mock_charm(
+            "name: foo\nsummary: foo\ndescription: foo\nresources:\n  foo-image:\n    type: oci-image\n    upstream-source: foo/foo:latest\n  bar-image:\n    type: oci-image\n  data:\n    type: file\n    filename: data.tar\n",
+            |cmd, args| {
+            if args[2] == "foo/foo:typo" {
+                return Err(JujuError::SubcommandError(
+                    format!("{} {}", cmd, args.join(" ")),
+                    "no such manifest: docker.io/foo/foo:typo\n".into(),
+                ));
+            }
+            Ok(b"{}".to_vec())
+        },
+        );
+
+        charm.check_images(&HashMap::new()).unwrap();
+        assert_eq!(
+            runner.calls(),
+            [["docker", "manifest", "inspect", "foo/foo:latest"]]
+        );
+
+        let resources = [
+            ("foo-image".to_string(), "foo/foo:typo".to_string()),
+            ("bar-image".to_string(), "bar/bar:1.0".to_string()),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let errors = charm.check_images(&resources).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "Image `foo/foo:typo` for resource foo-image can't be resolved: no such manifest: docker.io/foo/foo:typo"
+        );
+    }
+
+    #[test]
+    fn test_upload_publish_as() {
+        let dir = TempDir::new().unwrap();
+        write(dir.path().join("metadata.yaml"), METADATA).unwrap();
+        write(
+            dir.path().join("charmcraft.yaml"),
+            r#"
+type: charm
+bases:
+  - build-on: [{name: ubuntu, channel: "20.04"}]
+    run-on: [{name: ubuntu, channel: "20.04"}]
+  - build-on: [{name: ubuntu, channel: "22.04"}]
+    run-on: [{name: ubuntu, channel: "22.04"}]
+"#,
+        )
+        .unwrap();
+
+        let runner = MockRunner::new(|cmd, args| {
+            let output = match (cmd, args[0].as_str()) {
+                ("charmcraft", "resource-revisions") => r#"[{"revision": 3}]"#,
+                ("charmcraft", "upload") => "Revision 7 of 'foo-fork' created\n",
+                _ => "",
+            };
+            Ok(output.as_bytes().to_vec())
+        });
+
+        let charm = CharmSource::load(dir.path())
+            .unwrap()
+            .with_publish_as(Some("foo-fork"))
+            .with_runner(runner.clone());
+
+        let report = charm
+            .upload_charmhub(&HashMap::new(), &["edge".into()], false)
+            .unwrap();
+        assert_eq!(report.charm, "foo-fork");
+        assert_eq!(report.revision_url, "foo-fork-7");
+
+        let calls = runner.calls();
+        let charmcraft: Vec<_> = calls
+            .iter()
+            .filter(|c| c[0] == "charmcraft" && c[1] != "pack")
+            .collect();
+        assert!(!charmcraft.is_empty());
+        for call in charmcraft {
+            match call[1].as_str() {
+                "upload" => assert!(call.contains(&"--name=foo-fork".to_string()), "{:?}", call),
+                _ => assert_eq!(call[2], "foo-fork", "{:?}", call),
+            }
+        }
+        assert!(calls.iter().any(|c| c[1] == "upload-resource"));
+        assert!(calls.iter().any(|c| c[1] == "release"));
+    }
+
+    #[test]
+    fn test_load_container_run_as() {
+        let dir = TempDir::new().unwrap();
+        write_charm(
+            dir.path(),
+            "name: foo\nsummary: foo\ndescription: foo\ncharm-user: non-root\ncontainers:\n  app:\n    resource: app-image\n    run-as: app\nresources:\n  app-image:\n    type: oci-image\n",
+        );
+
+        let charm = CharmSource::load(dir.path()).unwrap();
+        assert_eq!(charm.metadata.charm_user, Some(CharmUser::NonRoot));
+        let container = &charm.metadata.containers["app"];
+        assert_eq!(container.run_as(), Some("app"));
+    }
+
+    #[test]
+    fn test_publish() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+
+        for already_registered in &[false, true] {
+            let runner = MockRunner::new(move |cmd, args| {
+                if cmd == "charmcraft" && args[0] == "register" && *already_registered {
+                    return Err(JujuError::SubcommandError(
+                        format!("{} {}", cmd, args.join(" ")),
+                        "Name foo is already registered.\n".into(),
+                    ));
+                }
+                Ok(canned_upload(cmd, args, 3))
+            });
+
+            let charm = CharmSource::load(dir.path())
+                .unwrap()
+                .with_runner(runner.clone());
+
+            let report = charm
+                .publish(&["edge".into()], &HashMap::new(), false)
+                .unwrap();
+            assert_eq!(report.revision_url, "foo-7");
+
+            let steps: Vec<_> = runner
+                .calls()
+                .into_iter()
+                .filter(|c| c[0] == "charmcraft")
+                .map(|c| c[1].clone())
+                .collect();
+            assert_eq!(
+                steps,
+                [
+                    "register",
+                    "pack",
+                    "upload-resource",
+                    "resource-revisions",
+                    "upload"
+                ]
+            );
+
+            let calls = runner.calls();
+            let upload = calls.iter().find(|c| c[1] == "upload").unwrap();
+            assert!(upload.contains(&"--release=edge".to_string()));
+            assert!(upload.contains(&"--resource=foo-image:3".to_string()));
+        }
+
+        let runner = MockRunner::new(|cmd, args| {
+            Err(JujuError::SubcommandError(
+                format!("{} {}", cmd, args.join(" ")),
+                "Not logged in\n".into(),
+            ))
+        });
+        let charm = CharmSource::load(dir.path())
+            .unwrap()
+            .with_runner(runner.clone());
+        assert!(charm.publish(&[], &HashMap::new(), false).is_err());
+        assert_eq!(runner.calls(), [["charmcraft", "register", "foo"]]);
+    }
+
+    #[test]
+    fn test_load_empty_action() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        write(
+            dir.path().join("actions.yaml"),
+            "restart:\nbackup:\n  description: Backs up the database\n",
+        )
+        .unwrap();
+
+        for options in &[LoadOptions { strict: false }, LoadOptions { strict: true }] {
+            let charm = CharmSource::load_with(dir.path(), options).unwrap();
+            let actions = charm.actions.unwrap();
+            assert_eq!(actions["restart"], Action::default());
+            assert_eq!(
+                actions["backup"].description.as_deref(),
+                Some("Backs up the database")
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_action() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        write(
+            dir.path().join("actions.yaml"),
+            r#"
+backup:
+  description: Backs up the database
+  params:
+    target: {type: string}
+    compress: {type: boolean}
+  required: [target]
+  additionalProperties: false
+"#,
+        )
+        .unwrap();
+
+        let runner = MockRunner::new(|_, _| {
+            Ok(b"foo/0:\n  id: \"2\"\n  results:\n    path: /backups/1.tar\n    return-code: 0\n  status: completed\n".to_vec())
+        });
+        let charm = CharmSource::load(dir.path())
+            .unwrap()
+            .with_runner(runner.clone());
+
+        let params: HashMap<_, _> = [
+            ("target".to_string(), "/backups".to_string()),
+            ("compress".to_string(), "true".to_string()),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let results = charm.run_action("foo", 0, "backup", &params).unwrap();
+        assert_eq!(results["path"], serde_yaml::Value::from("/backups/1.tar"));
+        assert_eq!(
+            runner.calls(),
+            [[
+                "juju",
+                "run",
+                "foo/0",
+                "backup",
+                "compress=true",
+                "target=/backups",
+                "--format",
+                "yaml",
+                "--wait"
+            ]]
+        );
+
+        assert!(matches!(
+            charm.run_action("foo", 0, "restore", &params),
+            Err(JujuError::UnknownAction(_, action)) if action == "restore"
+        ));
+        assert!(matches!(
+            charm.run_action("foo", 0, "backup", &HashMap::new()),
+            Err(JujuError::InvalidActionParams(_, reason)) if reason.contains("target")
+        ));
+
+        let mut extra = params.clone();
+        extra.insert("level".into(), "9".into());
+        assert!(matches!(
+            charm.run_action("foo", 0, "backup", &extra),
+            Err(JujuError::InvalidActionParams(_, reason)) if reason.contains("level")
+        ));
+        assert_eq!(runner.calls().len(), 1);
+    }
+
+    #[test]
+    fn test_build_verbosity() {
+        let (_dir, charm, runner) = mock_charm(METADATA, |_, _| Ok(vec![]));
+
+        charm.build(false).unwrap();
+        assert!(!runner.calls()[0]
+            .iter()
+            .any(|a| a.starts_with("--verbosity")));
+
+        let levels = [
+            (Verbosity::Quiet, "--verbosity=quiet"),
+            (Verbosity::Brief, "--verbosity=brief"),
+            (Verbosity::Verbose, "--verbosity=verbose"),
+            (Verbosity::Debug, "--verbosity=debug"),
+            (Verbosity::Trace, "--verbosity=trace"),
+        ];
+        for (verbosity, flag) in &levels {
+            charm.build_with_verbosity(true, *verbosity).unwrap();
+            let calls = runner.calls();
+            let call = calls.last().unwrap();
+            assert_eq!(call[call.len() - 2..], ["--destructive-mode", *flag]);
+        }
+    }
+
+    #[test]
+    fn test_set_resource_source() {
+        let dir = TempDir::new().unwrap();
+        let metadata = "# Maintained by the foo team\nname: foo\nsummary: A foo charm\ndescription: Does foo things\nresources:\n  foo-image:\n    type: oci-image\n    upstream-source: foo/foo:1.0  # bumped by CI\n  data:\n    type: file\n    filename: data.tar\n";
+        write_charm(dir.path(), metadata);
+
+        let charm = CharmSource::load(dir.path()).unwrap();
+        charm
+            .set_resource_source("foo-image", "foo/foo:1.1")
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("metadata.yaml")).unwrap(),
+            metadata.replace("foo/foo:1.0", "foo/foo:1.1")
+        );
+        let reloaded = CharmSource::load(dir.path()).unwrap();
+        assert_eq!(
+            reloaded.metadata.resource_origins()["foo-image"].as_deref(),
+            Some("foo/foo:1.1")
+        );
+
+        assert!(matches!(
+            charm.set_resource_source("data", "foo/foo:1.1"),
+            Err(JujuError::ResourceNotFound(name, _)) if name == "data"
+        ));
+    }
+
+    #[test]
+    fn test_python_requirements() {
+        let dir = TempDir::new().unwrap();
+        write_charm(dir.path(), METADATA);
+        assert!(CharmSource::load(dir.path())
+            .unwrap()
+            .python_requirements()
+            .unwrap()
+            .is_empty());
+
+        write(
+            dir.path().join("requirements.txt"),
+            "# Pinned by pip-compile\nops==2.9.0\n-r requirements-extra.txt\n\njinja2==3.1.2 \\\n    --hash=sha256:abc\npydantic>=1.10,<2  # not pinned yet\n",
+        )
+        .unwrap();
+        write(
+            dir.path().join("requirements-extra.txt"),
+            "--index-url https://pypi.org/simple\nrequests==2.31.0\n-r requirements.txt\n",
+        )
+        .unwrap();
+
+        let requirements = CharmSource::load(dir.path())
+            .unwrap()
+            .python_requirements()
+            .unwrap();
+        let pins: Vec<_> = requirements
+            .iter()
+            .map(|r| (r.name.as_str(), r.pinned_version()))
+            .collect();
+        assert_eq!(
+            pins,
+            [
+                ("ops", Some("2.9.0")),
+                ("requests", Some("2.31.0")),
+                ("jinja2", Some("3.1.2")),
+                ("pydantic", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_equivalent() {
+        let (a, b) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        write_charm(a.path(), METADATA);
+        write_charm(b.path(), METADATA);
+        write(
+            b.path().join("manifest.yaml"),
+            "charmcraft-version: 2.5.0\n",
+        )
+        .unwrap();
+
+        let charm_a = CharmSource::load(a.path()).unwrap();
+        let charm_b = CharmSource::load(b.path()).unwrap();
+        assert_ne!(charm_a.source, charm_b.source);
+        assert!(charm_a.is_equivalent(&charm_b));
+
+        write_charm(b.path(), &METADATA.replace("foo/foo:latest", "foo/foo:1.0"));
+        let charm_b = CharmSource::load(b.path()).unwrap();
+        assert!(!charm_a.is_equivalent(&charm_b));
+    }
+
+    #[test]
+    fn test_with_runner_upload() {
+        let (dir, charm, runner) = mock_charm(METADATA, |cmd, args| {
+            let output = match (cmd, args[0].as_str()) {
+                ("git", _) => "0123abcd\n",
+                ("docker", "image") => "[\"foo/foo@sha256:abc\"]\n",
+                ("charmcraft", "resource-revisions") => {
+                    r#"[{"revision": 3, "digest": "sha256:old"}]"#
+                }
+                ("charmcraft", "upload") => "Revision 7 of 'foo' created\n",
+                _ => "",
+            };
+            Ok(output.as_bytes().to_vec())
+        });
+
+        charm
+            .upload_charmhub(&HashMap::new(), &["edge".into()], false)
+            .unwrap();
+
+        let source = dir.path().to_string_lossy().to_string();
+        let artifact = charm.artifact_paths()[0].to_string_lossy().to_string();
+        assert_eq!(
+            runner.calls(),
+            [
+                vec!["charmcraft", "pack", "-p", &source],
+                vec![
+                    "docker",
+                    "image",
+                    "inspect",
+                    "--format={{json .RepoDigests}}",
+                    "foo/foo:latest"
+                ],
+                vec![
+                    "charmcraft",
+                    "resource-revisions",
+                    "foo",
+                    "foo-image",
+                    "--format",
+                    "json"
+                ],
+                vec![
+                    "charmcraft",
+                    "upload-resource",
+                    "foo",
+                    "foo-image",
+                    "--image",
+                    "foo/foo:latest"
+                ],
+                vec![
+                    "charmcraft",
+                    "resource-revisions",
+                    "foo",
+                    "foo-image",
+                    "--format",
+                    "json"
+                ],
+                vec![
+                    "charmcraft",
+                    "upload",
+                    "--quiet",
+                    &artifact,
+                    "--release=edge",
+                    "--resource=foo-image:3",
+                ],
+                vec!["git", "-C", &source, "rev-parse", "HEAD"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resources_with_order() {
+        let dir = TempDir::new().unwrap();
+        write_charm(
+            dir.path(),
+            &format!(
+                "{}  data:\n    type: file\n    filename: data.tar\n",
+                METADATA.trim_start()
+            ),
+        );
+        let charm = CharmSource::load(dir.path()).unwrap();
+
+        let configured: HashMap<_, _> = [
+            ("foo-image".to_string(), "mirror/foo:latest".to_string()),
+            ("data".to_string(), "./data.tar".to_string()),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        let resolved = charm
+            .resources_with_order(&configured, ResolutionOrder::OverrideFirst)
+            .unwrap();
+        assert_eq!(resolved["foo-image"], "mirror/foo:latest");
+        assert_eq!(resolved["data"], "./data.tar");
+        assert_eq!(
+            resolved,
+            charm.resources_with_defaults(&configured).unwrap()
+        );
+
+        let resolved = charm
+            .resources_with_order(&configured, ResolutionOrder::DefaultFirst)
+            .unwrap();
+        assert_eq!(resolved["foo-image"], "foo/foo:latest");
+        assert_eq!(resolved["data"], "./data.tar");
     }
 }
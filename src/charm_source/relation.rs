@@ -1,5 +1,8 @@
+use log::warn;
 use serde_derive::{Deserialize, Serialize};
 
+use crate::error::JujuError;
+
 /// Scope of a given relation
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "kebab-case")]
@@ -51,3 +54,180 @@ pub struct Relation {
     #[serde(default)]
     pub versions: Vec<String>,
 }
+
+/// Relation endpoints are often referred to by the interface they implement
+pub type Interface = Relation;
+
+impl Relation {
+    /// Whether this endpoint can be related to `other`
+    ///
+    /// Both sides must implement the same interface, and must share at least one schema version.
+    /// An endpoint that doesn't list any versions accepts all of them.
+    pub fn is_compatible_with(&self, other: &Relation) -> bool {
+        if self.interface != other.interface {
+            return false;
+        }
+
+        if self.versions.is_empty() || other.versions.is_empty() {
+            return true;
+        }
+
+        self.versions.iter().any(|v| other.versions.contains(v))
+    }
+
+    /// The interface name in Juju's canonical form, lowercase and hyphenated
+    pub fn normalized_name(&self) -> String {
+        self.interface.to_lowercase().replace('_', "-")
+    }
+
+    /// Checks that the interface name is already in its canonical form
+    ///
+    /// If not, `strict` makes this an error, and otherwise a warning is logged. Returns the
+    /// normalized name.
+    pub fn validate_name(&self, strict: bool) -> Result<String, JujuError> {
+        let normalized = self.normalized_name();
+
+        if normalized != self.interface {
+            if strict {
+                return Err(JujuError::NonCanonicalInterface(
+                    self.interface.clone(),
+                    normalized,
+                ));
+            }
+            warn!(
+                "Interface `{}` should be named `{}`",
+                self.interface, normalized
+            );
+        }
+
+        Ok(normalized)
+    }
+
+    /// Checks that `schema`, if set, is a well-formed reference to a schema document
+    ///
+    /// Schemas may be an `http(s)` URL with a host and path, or a relative path to a YAML or JSON
+    /// file. The schema itself isn't fetched.
+    pub fn validate_schema(&self) -> Result<(), JujuError> {
+        let schema = match &self.schema {
+            Some(schema) => schema,
+            None => return Ok(()),
+        };
+
+        let is_document = |path: &str| {
+            [".yaml", ".yml", ".json"]
+                .iter()
+                .any(|ext| path.ends_with(ext))
+        };
+
+        let well_formed = if schema.is_empty() || schema.contains(char::is_whitespace) {
+            false
+        } else if let Some(rest) = schema
+            .strip_prefix("https://")
+            .or_else(|| schema.strip_prefix("http://"))
+        {
+            matches!(rest.split_once('/'), Some((host, path)) if !host.is_empty() && !path.is_empty())
+        } else {
+            !schema.contains("://") && !schema.starts_with('/') && is_document(schema)
+        };
+
+        if well_formed {
+            Ok(())
+        } else {
+            Err(JujuError::InvalidSchema(
+                self.interface.clone(),
+                schema.clone(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relation(interface: &str, versions: &[&str]) -> Relation {
+        Relation {
+            interface: interface.into(),
+            versions: versions.iter().map(|v| v.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_compatible_versions() {
+        let a = relation("ingress", &["v1", "v2"]);
+        let b = relation("ingress", &["v2", "v3"]);
+
+        assert!(a.is_compatible_with(&b));
+        assert!(b.is_compatible_with(&a));
+        assert!(!a.is_compatible_with(&relation("mysql", &["v2"])));
+    }
+
+    #[test]
+    fn test_disjoint_versions() {
+        let a = relation("ingress", &["v1"]);
+        let b = relation("ingress", &["v2"]);
+
+        assert!(!a.is_compatible_with(&b));
+    }
+
+    #[test]
+    fn test_empty_versions_wildcard() {
+        let a = relation("ingress", &[]);
+        let b = relation("ingress", &["v2"]);
+
+        assert!(a.is_compatible_with(&b));
+        assert!(b.is_compatible_with(&a));
+    }
+
+    #[test]
+    fn test_validate_schema() {
+        let with_schema = |schema: Option<&str>| Relation {
+            interface: "ingress".into(),
+            schema: schema.map(String::from),
+            ..Default::default()
+        };
+
+        for schema in &[
+            None,
+            Some(
+                "https://raw.githubusercontent.com/canonical/operator-schemas/master/ingress.yaml",
+            ),
+            Some("schemas/ingress.json"),
+        ] {
+            with_schema(*schema).validate_schema().unwrap();
+        }
+
+        for schema in &[
+            "",
+            "https://",
+            "ftp://example.com/ingress.yaml",
+            "not a url",
+            "ingress",
+        ] {
+            assert!(matches!(
+                with_schema(Some(schema)).validate_schema(),
+                Err(JujuError::InvalidSchema(interface, s)) if interface == "ingress" && s == *schema
+            ));
+        }
+    }
+
+    #[test]
+    fn test_normalized_name() {
+        let underscored = relation("prometheus_scrape", &[]);
+        assert_eq!(underscored.normalized_name(), "prometheus-scrape");
+        assert_eq!(
+            underscored.validate_name(false).unwrap(),
+            "prometheus-scrape"
+        );
+        assert!(matches!(
+            underscored.validate_name(true),
+            Err(JujuError::NonCanonicalInterface(name, normalized))
+                if name == "prometheus_scrape" && normalized == "prometheus-scrape"
+        ));
+
+        let canonical = relation("ingress", &[]);
+        assert_eq!(canonical.normalized_name(), "ingress");
+        assert_eq!(canonical.validate_name(true).unwrap(), "ingress");
+    }
+}
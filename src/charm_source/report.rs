@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::to_string_pretty;
+
+use crate::error::JujuError;
+
+/// Machine-readable summary of a charm upload
+///
+/// Intended for CI pipelines that want to record what was published without scraping logs.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct UploadReport {
+    /// Name of the charm that was uploaded
+    pub charm: String,
+
+    /// Charm URL of the uploaded revision, e.g. `foo-42`
+    pub revision_url: String,
+
+    /// Charm URL of the revision uploaded for each base, keyed like `ubuntu-20.04-amd64`
+    ///
+    /// Charms with several bases upload one `.charm`, and so get one revision, per base.
+    pub base_revisions: HashMap<String, String>,
+
+    /// Revision of each resource uploaded alongside the charm
+    pub resource_revisions: HashMap<String, u32>,
+
+    /// Channels that the revision was released to
+    pub channels: Vec<String>,
+
+    /// Commit of the charm's source repository, if it is a git checkout
+    pub git_commit: Option<String>,
+
+    /// How long the build and upload took, in seconds
+    pub duration_secs: f64,
+}
+
+impl UploadReport {
+    /// Serializes the report as JSON
+    pub fn to_json(&self) -> Result<String, JujuError> {
+        Ok(to_string_pretty(self)?)
+    }
+}
+
+/// How far along the upload of a resource is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceProgress {
+    /// Name of the resource being uploaded
+    pub resource: String,
+
+    /// Percentage uploaded so far, from 0 to 100
+    pub percent: u8,
+}
+
+impl ResourceProgress {
+    /// Parses a progress line from charmcraft, such as `Uploading... 45.2%`
+    ///
+    /// Returns `None` for lines without a percentage.
+    pub(crate) fn parse(resource: &str, line: &str) -> Option<Self> {
+        let number = line[..line.rfind('%')?]
+            .rsplit(|ch: char| !(ch.is_ascii_digit() || ch == '.'))
+            .next()?;
+        let percent = number.parse::<f64>().ok()?;
+
+        Some(Self {
+            resource: resource.into(),
+            percent: percent.clamp(0.0, 100.0) as u8,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_progress() {
+        let parse = |line| ResourceProgress::parse("foo-image", line).map(|p| p.percent);
+
+        assert_eq!(parse("Uploading... 45.2%"), Some(45));
+        assert_eq!(parse("[=====>    ] 100%"), Some(100));
+        assert_eq!(parse("Revision 4 created"), None);
+        assert_eq!(parse("%"), None);
+    }
+}
@@ -0,0 +1,96 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// A Python package that the charm depends on, from its `requirements.txt`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Requirement {
+    /// Name of the package, e.g. `ops`
+    pub name: String,
+
+    /// Extras of the package, e.g. `testing` for `ops[testing]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extras: Vec<String>,
+
+    /// Which versions are allowed, e.g. `==2.0.0` or `>=1.5,<2`, or a URL such as
+    /// `@ git+https://...`
+    pub specifier: Option<String>,
+}
+
+impl Requirement {
+    /// Parses a single requirement line, ignoring environment markers and `--hash` options
+    ///
+    /// Returns `None` for blank lines, comments, and options such as `-r` or `--index-url`.
+    pub(crate) fn parse(line: &str) -> Option<Self> {
+        let line = match line.find(" #") {
+            Some(at) => &line[..at],
+            None => line,
+        };
+        let line = line.split(';').next()?.trim();
+        if line.is_empty() || line.starts_with(['#', '-']) {
+            return None;
+        }
+
+        let line = line.split(" --").next()?.trim();
+        let name_end = line
+            .find(|ch: char| !(ch.is_ascii_alphanumeric() || "._-".contains(ch)))
+            .unwrap_or(line.len());
+        let (name, mut rest) = line.split_at(name_end);
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut extras = vec![];
+        if let Some(bracketed) = rest.trim_start().strip_prefix('[') {
+            let (inner, after) = bracketed.split_once(']')?;
+            extras = inner
+                .split(',')
+                .map(str::trim)
+                .filter(|extra| !extra.is_empty())
+                .map(String::from)
+                .collect();
+            rest = after;
+        }
+
+        let specifier: String = rest.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        Some(Self {
+            name: name.into(),
+            extras,
+            specifier: Some(specifier).filter(|s| !s.is_empty()),
+        })
+    }
+
+    /// The exact version that the package is pinned to with `==`, if it is
+    pub fn pinned_version(&self) -> Option<&str> {
+        let version = self.specifier.as_deref()?.strip_prefix("==")?.trim();
+        Some(version).filter(|v| !v.contains([',', '*']))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let requirement =
+            Requirement::parse("ops[testing, harness] == 2.9.0 ; python_version >= \"3.8\"")
+                .unwrap();
+        assert_eq!(requirement.name, "ops");
+        assert_eq!(requirement.extras, ["testing", "harness"]);
+        assert_eq!(requirement.specifier.as_deref(), Some("== 2.9.0"));
+
+        let requirement =
+            Requirement::parse("jinja2==3.1.2 --hash=sha256:abc  # templates").unwrap();
+        assert_eq!(requirement.pinned_version(), Some("3.1.2"));
+
+        let requirement = Requirement::parse("pydantic>=1.10,<2").unwrap();
+        assert_eq!(requirement.specifier.as_deref(), Some(">=1.10,<2"));
+        assert_eq!(requirement.pinned_version(), None);
+
+        assert_eq!(Requirement::parse("requests").unwrap().specifier, None);
+        assert_eq!(Requirement::parse("# comment"), None);
+        assert_eq!(Requirement::parse("-r base.txt"), None);
+        assert_eq!(Requirement::parse("   "), None);
+    }
+}
@@ -1,5 +1,28 @@
 use serde_derive::{Deserialize, Serialize};
 
+fn default_dockerfile() -> String {
+    "Dockerfile".into()
+}
+
+fn default_context() -> String {
+    ".".into()
+}
+
+/// How to build an oci-image resource locally instead of pulling it
+///
+/// Paths are relative to the charm's source directory.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ImageBuild {
+    /// Path to the Dockerfile
+    #[serde(default = "default_dockerfile")]
+    pub dockerfile: String,
+
+    /// Directory to use as the build context
+    #[serde(default = "default_context")]
+    pub context: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "kebab-case", tag = "type")]
 pub enum Resource {
@@ -21,5 +44,79 @@ pub enum Resource {
         /// Juju is weird about this though for reasons, see
         /// https://bugs.launchpad.net/juju/+bug/1946121
         upstream_source: Option<String>,
+
+        /// If set, the image is built locally at upload time, and takes precedence over
+        /// `upstream_source`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        build: Option<ImageBuild>,
     },
+
+    /// A resource type that this library doesn't know about yet
+    #[serde(other)]
+    Unknown,
+}
+
+impl Resource {
+    /// Whether this resource has one of the types that this library understands
+    pub fn is_known_type(&self) -> bool {
+        !matches!(self, Resource::Unknown)
+    }
+}
+
+/// Which wins when a resource is both configured, e.g. in a bundle, and has a default
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResolutionOrder {
+    /// The configured value overrides the default
+    #[default]
+    OverrideFirst,
+
+    /// The default is used where there is one, and the configured value only fills in for
+    /// resources without, e.g. when mirroring a charm's declared images
+    DefaultFirst,
+}
+
+/// An oci-image resource whose declared image differs from what's released
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ResourceDrift {
+    /// Name of the resource
+    pub resource: String,
+
+    /// The `upstream-source` declared in metadata.yaml
+    pub declared: Option<String>,
+
+    /// The released resource revision, if any
+    pub released_revision: Option<u32>,
+
+    /// Where the released revision came from, if the store says
+    pub released_origin: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_yaml::from_str;
+
+    use super::*;
+
+    #[test]
+    fn test_unknown_type() {
+        let resources: HashMap<String, Resource> = from_str(
+            r#"
+image:
+  type: oci-image
+  upstream-source: foo/foo:latest
+novel:
+  type: quantum-blob
+  description: Something from the future
+"#,
+        )
+        .unwrap();
+
+        assert!(resources["image"].is_known_type());
+        assert_eq!(resources["novel"], Resource::Unknown);
+        assert!(!resources["novel"].is_known_type());
+    }
 }
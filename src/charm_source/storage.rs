@@ -1,5 +1,7 @@
 use serde_derive::{Deserialize, Serialize};
 
+use crate::size::Size;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "kebab-case", tag = "type")]
 pub enum Storage {
@@ -29,9 +31,9 @@ pub enum Storage {
 
         /// Minimum size of requested storage in forms G, GiB, GB
         ///
-        /// Size multipliers are M, G, T, P, E, Z or Y. With no multiplier supplied, M is implied.
+        /// Size multipliers are K, M, G, T, P or E. With no multiplier supplied, M is implied.
         #[serde(default)]
-        minimum_size: Option<String>,
+        minimum_size: Option<Size>,
 
         /// List of properties, only supported value is "transient"
         #[serde(default)]
@@ -63,9 +65,9 @@ pub enum Storage {
 
         /// Minimum size of requested storage in forms G, GiB, GB
         ///
-        /// Size multipliers are M, G, T, P, E, Z or Y. With no multiplier supplied, M is implied.
+        /// Size multipliers are K, M, G, T, P or E. With no multiplier supplied, M is implied.
         #[serde(default)]
-        minimum_size: Option<String>,
+        minimum_size: Option<Size>,
 
         /// List of properties, only supported value is "transient"
         #[serde(default)]
@@ -0,0 +1,310 @@
+//! A YAML emitter that keeps multi-line strings readable, and an in-place editor
+//!
+//! serde_yaml writes multi-line strings as double-quoted scalars full of `\n` escapes, which
+//! mangles the long descriptions found in charm YAML files. This writes them as `|` block
+//! scalars instead, with everything else emitted the way serde_yaml would.
+//!
+//! Round-tripping through serde_yaml also drops comments, so `set_scalar` edits a single value
+//! by rewriting only its line.
+
+use serde_yaml::Value;
+
+use crate::error::JujuError;
+
+/// Serializes `value` as a YAML document, without the leading `---`
+pub(crate) fn to_string(value: &Value) -> Result<String, JujuError> {
+    let mut out = String::new();
+    emit(value, 0, &mut out)?;
+    Ok(out)
+}
+
+fn emit(value: &Value, indent: usize, out: &mut String) -> Result<(), JujuError> {
+    match value {
+        Value::Mapping(mapping) if !mapping.is_empty() => {
+            for (key, value) in mapping {
+                out.push_str(&" ".repeat(indent));
+                out.push_str(&scalar(key)?);
+                out.push(':');
+                emit_nested(value, indent, out)?;
+            }
+        }
+        Value::Sequence(sequence) if !sequence.is_empty() => {
+            for item in sequence {
+                let mut nested = String::new();
+                emit(item, indent + 2, &mut nested)?;
+                if matches!(item, Value::Mapping(m) if !m.is_empty())
+                    || matches!(item, Value::Sequence(s) if !s.is_empty())
+                {
+                    out.push_str(&" ".repeat(indent));
+                    out.push_str("- ");
+                    out.push_str(&nested[indent + 2..]);
+                } else {
+                    out.push_str(&" ".repeat(indent));
+                    out.push('-');
+                    emit_nested(item, indent, out)?;
+                }
+            }
+        }
+        other => {
+            out.push_str(&" ".repeat(indent));
+            out.push_str(&scalar(other)?);
+            out.push('\n');
+        }
+    }
+
+    Ok(())
+}
+
+/// Emits the value of a mapping entry or sequence item, after its `key:` or `-`
+fn emit_nested(value: &Value, indent: usize, out: &mut String) -> Result<(), JujuError> {
+    match value {
+        Value::Mapping(m) if !m.is_empty() => {
+            out.push('\n');
+            emit(value, indent + 2, out)
+        }
+        Value::Sequence(s) if !s.is_empty() => {
+            out.push('\n');
+            emit(value, indent + 2, out)
+        }
+        Value::String(s) if is_block(s) => {
+            block_scalar(s, indent + 2, out);
+            Ok(())
+        }
+        other => {
+            out.push(' ');
+            out.push_str(&scalar(other)?);
+            out.push('\n');
+            Ok(())
+        }
+    }
+}
+
+/// Whether a string can be written as a block scalar without changing its value
+fn is_block(s: &str) -> bool {
+    s.contains('\n')
+        && !s.starts_with('\n')
+        && !s
+            .chars()
+            .any(|ch| ch != '\n' && (ch.is_control() || ch == '\u{feff}'))
+        && s.lines().all(|line| line.trim_end() == line)
+}
+
+/// Writes `s` as a `|` block scalar, picking the chomping indicator that preserves its
+/// trailing newlines
+fn block_scalar(s: &str, indent: usize, out: &mut String) {
+    let body = s.trim_end_matches('\n');
+    let trailing = s.len() - body.len();
+
+    out.push_str(" |");
+    if body.starts_with(' ') {
+        out.push('2');
+    }
+    match trailing {
+        0 => out.push('-'),
+        1 => {}
+        _ => out.push('+'),
+    }
+    out.push('\n');
+
+    for line in body.split('\n') {
+        if !line.is_empty() {
+            out.push_str(&" ".repeat(indent));
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+
+    for _ in 1..trailing {
+        out.push('\n');
+    }
+}
+
+/// Sets the scalar at `path`, e.g. `["resources", "foo-image", "upstream-source"]`, to `value`
+///
+/// Only the value's line changes, keeping comments and key order everywhere else. A missing
+/// final key is added as the first entry of its parent. Only block-style mappings can be
+/// walked, so the reason is returned as an error if e.g. a flow mapping is in the way.
+pub(crate) fn set_scalar(text: &str, path: &[&str], value: &Value) -> Result<String, String> {
+    let mut lines: Vec<String> = text.split_inclusive('\n').map(String::from).collect();
+    let value = match value {
+        Value::String(s) if is_plain(s) => s.clone(),
+        other => scalar(other).map_err(|err| err.to_string())?,
+    };
+    let (mut start, mut end) = (0, lines.len());
+    let mut parent_indent = None;
+
+    for (depth, key) in path.iter().enumerate() {
+        let is_last = depth + 1 == path.len();
+        let content = |line: &str| {
+            let trimmed = line.trim_start();
+            !trimmed.is_empty() && !trimmed.starts_with('#')
+        };
+        let indent_of = |line: &str| line.len() - line.trim_start().len();
+
+        let child_indent = lines[start..end]
+            .iter()
+            .find(|line| content(line))
+            .map(|line| indent_of(line))
+            .unwrap_or_else(|| parent_indent.map_or(0, |i| i + 2));
+        let prefix = format!("{}:", key);
+        let found = (start..end).find(|&i| {
+            let line = &lines[i];
+            content(line)
+                && indent_of(line) == child_indent
+                && line
+                    .trim_start()
+                    .strip_prefix(&prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\n', '\r', '#']))
+        });
+
+        let i = match (found, is_last) {
+            (Some(i), _) => i,
+            (None, true) => {
+                let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+                let line = format!(
+                    "{}{} {}{}",
+                    " ".repeat(child_indent),
+                    prefix,
+                    value,
+                    newline
+                );
+                lines.insert(start, line);
+                return Ok(lines.concat());
+            }
+            (None, false) => return Err(format!("`{}` not found", path[..=depth].join("."))),
+        };
+
+        let line = &lines[i];
+        let key_end = indent_of(line) + prefix.len();
+        let rest = &line[key_end..];
+        let body = rest.trim_end_matches(['\n', '\r']);
+        let newline = &rest[body.len()..];
+        let (inline, comment) = match body.find(" #") {
+            Some(at) => body.split_at(body[..at].trim_end().len()),
+            None => (body, ""),
+        };
+        let inline = inline.trim();
+
+        if is_last {
+            if inline.is_empty() || inline.starts_with(['{', '[', '|', '>', '&', '*', '!']) {
+                return Err(format!("`{}` isn't a plain scalar", path.join(".")));
+            }
+            let edited = format!("{} {}{}{}", &line[..key_end], value, comment, newline);
+            lines[i] = edited;
+            return Ok(lines.concat());
+        }
+
+        if !inline.is_empty() {
+            return Err(format!(
+                "`{}` isn't a block mapping",
+                path[..=depth].join(".")
+            ));
+        }
+
+        start = i + 1;
+        end = (start..end)
+            .find(|&j| content(&lines[j]) && indent_of(&lines[j]) <= child_indent)
+            .unwrap_or(end);
+        parent_indent = Some(child_indent);
+    }
+
+    Err("empty path".into())
+}
+
+/// Whether a string can be written unquoted, e.g. an image reference such as `foo/foo:1.0`
+///
+/// serde_yaml quotes anything containing a `:`, which is safe but noisy for an in-place edit.
+/// This is deliberately conservative, and also quotes words that YAML 1.1 reads as booleans.
+fn is_plain(s: &str) -> bool {
+    let yaml_1_1_bools = ["y", "n", "yes", "no", "on", "off", "true", "false"];
+
+    s.starts_with(|ch: char| ch.is_ascii_alphanumeric())
+        && s.chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || "/:._-@+".contains(ch))
+        && !yaml_1_1_bools.contains(&s.to_ascii_lowercase().as_str())
+        && serde_yaml::from_str::<Value>(s).ok() == Some(Value::String(s.into()))
+}
+
+/// Formats a scalar, or an empty collection, on a single line
+fn scalar(value: &Value) -> Result<String, JujuError> {
+    let emitted = serde_yaml::to_string(value)?;
+    let emitted = emitted.strip_prefix("---").unwrap_or(&emitted);
+
+    Ok(emitted.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::from_str;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let yaml = r#"name: foo
+description: |
+  Does foo things.
+
+    Including indented ones.
+summary: "yes"
+empty: {}
+tags:
+  - a
+  - b: c
+    d: 1
+tail: |+
+  keep
+
+"#;
+        let value: Value = from_str(yaml).unwrap();
+        let emitted = to_string(&value).unwrap();
+
+        assert_eq!(emitted, yaml);
+        assert_eq!(from_str::<Value>(&emitted).unwrap(), value);
+    }
+
+    #[test]
+    fn test_set_scalar() {
+        let yaml = "# The foo charm\nname: foo\nresources:\n  # The workload\n  foo-image:\n    type: oci-image\n    upstream-source: foo/foo:1.0  # pinned\n  bar-image:\n    type: oci-image\n    upstream-source: bar/bar:1.0\nsummary: foo\n";
+        let path = ["resources", "bar-image", "upstream-source"];
+
+        assert_eq!(
+            set_scalar(yaml, &path, &Value::from("bar/bar:2.0")).unwrap(),
+            yaml.replace("bar/bar:1.0", "bar/bar:2.0")
+        );
+        assert_eq!(
+            set_scalar(
+                yaml,
+                &["resources", "foo-image", "upstream-source"],
+                &Value::from("foo/foo:2.0")
+            )
+            .unwrap(),
+            yaml.replace("foo/foo:1.0", "foo/foo:2.0")
+        );
+        assert_eq!(
+            set_scalar(
+                yaml,
+                &["resources", "foo-image", "description"],
+                &Value::from("yes")
+            )
+            .unwrap(),
+            yaml.replace(
+                "    type: oci-image\n    upstream-source: foo",
+                "    description: \"yes\"\n    type: oci-image\n    upstream-source: foo"
+            )
+        );
+
+        assert!(set_scalar(
+            yaml,
+            &["resources", "baz-image", "upstream-source"],
+            &Value::from("x")
+        )
+        .is_err());
+        assert!(set_scalar(
+            "resources: {foo-image: {type: oci-image}}\n",
+            &path,
+            &Value::from("x")
+        )
+        .is_err());
+    }
+}
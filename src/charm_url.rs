@@ -1,5 +1,6 @@
 use std::convert::TryFrom;
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use nom::bytes::complete::tag;
@@ -9,6 +10,8 @@ use nom::sequence::{delimited, preceded, terminated, tuple};
 use nom::{Err as NomErr, IResult, Needed};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::error::JujuError;
+
 /// Matches a `kebab-case` name that must not start or end with a dash
 fn kebab_case(input: &str) -> IResult<&str, &str> {
     // Need some valid input
@@ -91,8 +94,25 @@ fn parse_cs_url(input: &str) -> IResult<&str, CharmURL> {
     })(input)
 }
 
+/// Maps an architecture name to the name Juju uses for it
+///
+/// For example `x86_64` becomes `amd64` and `aarch64` becomes `arm64`. Unrecognized names are
+/// passed through unchanged.
+pub fn normalize_architecture(arch: &str) -> &str {
+    match arch {
+        "x86_64" | "x86-64" | "x64" => "amd64",
+        "aarch64" | "arm64" => "arm64",
+        "armv7l" | "armv7" | "armhf" => "armhf",
+        "ppc64le" | "ppc64el" => "ppc64el",
+        "i686" | "i386" | "x86" => "i386",
+        other => other,
+    }
+}
+
 /// Represents a charm's charm store URL
-#[derive(Debug, PartialEq, Clone, Eq)]
+///
+/// Equality and hashing compare every part of the URL; use `same_charm` to ignore revisions.
+#[derive(Debug, PartialEq, Clone, Eq, Hash)]
 pub struct CharmURL {
     pub store: Option<String>,
     pub namespace: Option<String>,
@@ -115,6 +135,26 @@ impl CharmURL {
         Ok(url)
     }
 
+    /// Parses charm references separated by commas and/or whitespace, e.g. from a CLI argument
+    ///
+    /// Entries starting with `.` or `/` are local paths, as in bundles. The first invalid entry
+    /// is reported with its 1-based position in the list.
+    pub fn parse_list(input: &str) -> Result<Vec<Self>, JujuError> {
+        input
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|entry| !entry.is_empty())
+            .enumerate()
+            .map(|(i, entry)| {
+                if entry.starts_with(['.', '/']) {
+                    return Ok(CharmURL::from_path(entry));
+                }
+
+                CharmURL::parse(entry)
+                    .map_err(|err| JujuError::InvalidCharmURL(i + 1, entry.into(), err))
+            })
+            .collect()
+    }
+
     /// Returns the un-namespaced charm name, for passing to the API of a particular charm store
     pub fn api_name(&self) -> String {
         let mut temp = self.clone();
@@ -122,6 +162,68 @@ impl CharmURL {
         temp.to_string()
     }
 
+    /// Returns the architecture that a local `.charm` artifact was built for
+    ///
+    /// charmcraft names artifacts like `foo_ubuntu-20.04-amd64.charm`, so the architecture is the
+    /// last dash-separated component of the base. Store URLs and artifacts that aren't named like
+    /// that, e.g. `./foo.charm`, don't carry an architecture.
+    pub fn architecture(&self) -> Option<String> {
+        let file_name = self.name.rsplit('/').next()?;
+        let stem = file_name.strip_suffix(".charm")?;
+        let (_, base) = stem.rsplit_once('_')?;
+        let (_, arch) = base.rsplit_once('-')?;
+
+        Some(normalize_architecture(arch).to_string())
+    }
+
+    /// Whether both URLs name the same charm, regardless of store or revision
+    ///
+    /// Namespaces are only compared if both URLs have one.
+    pub fn same_charm(&self, other: &CharmURL) -> bool {
+        let namespaces_match = match (&self.namespace, &other.namespace) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        };
+
+        self.name == other.name && namespaces_match
+    }
+
+    /// Translates a legacy charm store URL such as `cs:foo-5` into its Charmhub form, `ch:foo`
+    ///
+    /// Charm store revisions don't correspond to Charmhub revisions, so they're dropped; the
+    /// result deploys whatever Charmhub has under the same name. URLs that are already Charmhub
+    /// URLs are returned as is.
+    ///
+    /// Fails with `NoCharmhubEquivalent` where the translation would be ambiguous. Charmhub has
+    /// no user namespaces, and a charm such as `cs:~user/foo` generally isn't the same charm as
+    /// the promulgated `foo`, so namespaced URLs are rejected. So are local charms and other
+    /// stores.
+    pub fn to_charmhub(&self) -> Result<CharmURL, JujuError> {
+        let untranslatable =
+            |reason: &str| JujuError::NoCharmhubEquivalent(self.to_string(), reason.into());
+
+        if self.is_local() {
+            return Err(untranslatable("it's a local charm"));
+        }
+
+        if self.namespace.is_some() {
+            return Err(untranslatable(
+                "Charmhub has no user namespaces, and it may not be the promulgated charm",
+            ));
+        }
+
+        match self.store.as_deref() {
+            Some("ch") => Ok(self.clone()),
+            Some("cs") | None => Ok(CharmURL {
+                store: Some("ch".into()),
+                namespace: None,
+                name: self.name.clone(),
+                revision: None,
+            }),
+            Some(_) => Err(untranslatable("it's from an unknown store")),
+        }
+    }
+
     pub fn from_path<P: Into<PathBuf>>(path: P) -> Self {
         CharmURL {
             store: None,
@@ -131,6 +233,26 @@ impl CharmURL {
         }
     }
 
+    /// Whether this refers to a charm on the local filesystem, such as `./foo.charm`
+    ///
+    /// Store URLs can only contain kebab-case names, so any name containing a path separator
+    /// or a `.` must have come from `from_path`.
+    pub fn is_local(&self) -> bool {
+        self.store.is_none()
+            && self.namespace.is_none()
+            && self.revision.is_none()
+            && self.name.contains(['/', '.'])
+    }
+
+    /// Returns the path of a local charm reference
+    pub fn as_local_path(&self) -> Option<&Path> {
+        if self.is_local() {
+            Some(Path::new(&self.name))
+        } else {
+            None
+        }
+    }
+
     pub fn with_store(&self, store: Option<String>) -> Self {
         CharmURL {
             store,
@@ -169,25 +291,23 @@ impl TryFrom<&str> for CharmURL {
     }
 }
 
-impl ToString for CharmURL {
-    fn to_string(&self) -> String {
-        let mut serialized = String::new();
-
+impl fmt::Display for CharmURL {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if let Some(st) = &self.store {
-            serialized.push_str(&format!("{}:", st))
+            write!(f, "{}:", st)?;
         }
 
         if let Some(ns) = &self.namespace {
-            serialized.push_str(&format!("~{}/", ns))
+            write!(f, "~{}/", ns)?;
         }
 
-        serialized.push_str(&self.name);
+        f.write_str(&self.name)?;
 
         if let Some(rev) = &self.revision {
-            serialized.push_str(&format!("-{}", rev))
+            write!(f, "-{}", rev)?;
         }
 
-        serialized
+        Ok(())
     }
 }
 
@@ -208,6 +328,11 @@ impl<'de> Deserialize<'de> for CharmURL {
         use serde::de::Error;
         let s = String::deserialize(deserializer)?;
 
+        // Bundles may point at local charms, e.g. `charm: ./foo`
+        if s.starts_with(['.', '/']) {
+            return Ok(CharmURL::from_path(s));
+        }
+
         s.parse()
             .map_err(|err| Error::custom(format!("Error deserializing CharmURL: {}", err)))
     }
@@ -365,6 +490,74 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_normalize_architecture() {
+        let aliases = [
+            ("x86_64", "amd64"),
+            ("x86-64", "amd64"),
+            ("x64", "amd64"),
+            ("amd64", "amd64"),
+            ("aarch64", "arm64"),
+            ("arm64", "arm64"),
+            ("armv7l", "armhf"),
+            ("armv7", "armhf"),
+            ("armhf", "armhf"),
+            ("ppc64le", "ppc64el"),
+            ("ppc64el", "ppc64el"),
+            ("i686", "i386"),
+            ("i386", "i386"),
+            ("x86", "i386"),
+            ("s390x", "s390x"),
+        ];
+
+        for (alias, expected) in &aliases {
+            assert_eq!(normalize_architecture(alias), *expected);
+        }
+    }
+
+    #[test]
+    fn test_architecture() {
+        let url = CharmURL::from_path("/tmp/foo_ubuntu-20.04-aarch64.charm");
+        assert_eq!(url.architecture(), Some("arm64".to_string()));
+
+        let url: CharmURL = "cs:foo-42".parse().unwrap();
+        assert_eq!(url.architecture(), None);
+
+        let url = CharmURL::from_path("./my-charm.charm");
+        assert_eq!(url.architecture(), None);
+
+        let url = CharmURL::from_path("/tmp/build_dir-2/foo.charm");
+        assert_eq!(url.architecture(), None);
+    }
+
+    #[test]
+    fn test_local_path() {
+        let url = CharmURL::from_path("./foo.charm");
+        assert!(url.is_local());
+        assert_eq!(url.as_local_path(), Some(Path::new("./foo.charm")));
+
+        let url = CharmURL::from_path("/tmp/charms/foo");
+        assert_eq!(url.as_local_path(), Some(Path::new("/tmp/charms/foo")));
+    }
+
+    #[test]
+    fn test_deserialize_local_path() {
+        let url: CharmURL = from_str("./charms/foo").unwrap();
+        assert_eq!(url.as_local_path(), Some(Path::new("./charms/foo")));
+
+        let url: CharmURL = from_str("/tmp/foo.charm").unwrap();
+        assert_eq!(url.as_local_path(), Some(Path::new("/tmp/foo.charm")));
+    }
+
+    #[test]
+    fn test_store_not_local() {
+        for url in &["cs:~foo/bar-42", "bar", "ch:bar"] {
+            let url: CharmURL = url.parse().unwrap();
+            assert!(!url.is_local());
+            assert_eq!(url.as_local_path(), None);
+        }
+    }
+
     #[test]
     fn test_serialization() {
         let charm_url = CharmURL {
@@ -381,4 +574,60 @@ mod tests {
         let parsed: CharmURL = from_str(serialized).unwrap();
         assert_eq!(parsed, charm_url);
     }
+
+    #[test]
+    fn test_same_charm() {
+        let foo = CharmURL::parse("cs:~me/foo-1").unwrap();
+
+        assert!(foo.same_charm(&CharmURL::parse("cs:~me/foo-2").unwrap()));
+        assert!(foo.same_charm(&CharmURL::parse("foo").unwrap()));
+        assert!(!foo.same_charm(&CharmURL::parse("cs:~you/foo-1").unwrap()));
+        assert!(!foo.same_charm(&CharmURL::parse("cs:~me/bar-1").unwrap()));
+
+        assert_ne!(foo, CharmURL::parse("cs:~me/foo-2").unwrap());
+        assert_eq!(foo, CharmURL::parse("cs:~me/foo-1").unwrap());
+
+        let mut seen = std::collections::HashSet::new();
+        seen.insert(foo.clone());
+        assert!(seen.contains(&CharmURL::parse("cs:~me/foo-1").unwrap()));
+        assert!(!seen.contains(&CharmURL::parse("cs:~me/foo-2").unwrap()));
+    }
+
+    #[test]
+    fn test_to_charmhub() {
+        let translate = |url: &str| CharmURL::parse(url).unwrap().to_charmhub();
+
+        assert!(matches!(
+            translate("cs:~user/charm-5"),
+            Err(JujuError::NoCharmhubEquivalent(url, _)) if url == "cs:~user/charm-5"
+        ));
+        assert_eq!(translate("cs:charm-5").unwrap().to_string(), "ch:charm");
+        assert_eq!(translate("cs:charm").unwrap().to_string(), "ch:charm");
+        assert_eq!(translate("ch:charm").unwrap().to_string(), "ch:charm");
+        assert!(translate("foo:charm").is_err());
+        assert!(CharmURL::from_path("./charm.charm").to_charmhub().is_err());
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let urls = CharmURL::parse_list("ch:foo, cs:~bar/baz-3\n./local.charm,,qux").unwrap();
+        assert_eq!(
+            urls,
+            [
+                CharmURL::parse("ch:foo").unwrap(),
+                CharmURL::parse("cs:~bar/baz-3").unwrap(),
+                CharmURL::from_path("./local.charm"),
+                CharmURL::parse("qux").unwrap(),
+            ]
+        );
+        assert!(CharmURL::parse_list(" ").unwrap().is_empty());
+
+        match CharmURL::parse_list("ch:foo,ch:Bad_Name,ch:bar") {
+            Err(JujuError::InvalidCharmURL(position, entry, _)) => {
+                assert_eq!(position, 2);
+                assert_eq!(entry, "ch:Bad_Name");
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,434 @@
+//! Parsing for Charmhub responses, as reported by `charmcraft`
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{from_slice, to_string_pretty};
+
+use crate::channel::Channel;
+use crate::error::JujuError;
+use crate::size::Size;
+
+/// Whether a channel has a release of its own
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReleaseStatus {
+    /// The channel has a revision released to it
+    Open,
+
+    /// The channel follows the next more stable channel
+    Tracking,
+
+    /// Nothing may be installed from the channel
+    Closed,
+}
+
+/// A resource revision attached to a released charm revision
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReleasedResource {
+    pub name: String,
+    pub revision: u32,
+
+    /// Where the revision came from, e.g. the image it was uploaded from, if the store says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+}
+
+/// A revision of a resource, as listed by `charmcraft resource-revisions --format json`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct ResourceRevision {
+    pub revision: u32,
+
+    /// Digest of the uploaded image, for oci-image resources
+    #[serde(default)]
+    pub digest: Option<String>,
+
+    /// Size of the uploaded file or image
+    #[serde(default, with = "crate::size::bytes")]
+    pub size: Option<Size>,
+}
+
+impl ResourceRevision {
+    /// Parses the JSON output of `charmcraft resource-revisions`
+    pub fn parse_all(json: &[u8]) -> Result<Vec<Self>, JujuError> {
+        Ok(from_slice(json)?)
+    }
+
+    /// Parses the table that `charmcraft resource-revisions` prints without `--format json`
+    ///
+    /// The first column is the revision, and the table has a header row. Digests aren't shown.
+    pub fn parse_table(text: &str) -> Result<Vec<Self>, JujuError> {
+        text.lines()
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().next())
+            .map(|revision| {
+                Ok(Self {
+                    revision: revision.parse().map_err(|_| {
+                        JujuError::SubcommandError(
+                            "charmcraft resource-revisions".into(),
+                            format!("unexpected revision `{}`", revision),
+                        )
+                    })?,
+                    digest: None,
+                    size: None,
+                })
+            })
+            .collect()
+    }
+
+    /// The newest of `revisions`, i.e. the one most recently uploaded
+    pub fn latest(revisions: &[Self]) -> Option<u32> {
+        revisions.iter().map(|r| r.revision).max()
+    }
+}
+
+/// An uploaded revision of a charm, as listed by `charmcraft revisions --format json`
+///
+/// Unlike `Status`, this includes revisions that were never released.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct CharmRevision {
+    pub revision: u32,
+
+    #[serde(default)]
+    pub version: Option<String>,
+
+    /// Review status of the revision, e.g. `approved`
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+impl CharmRevision {
+    /// Parses the JSON output of `charmcraft revisions`
+    pub fn parse_all(json: &[u8]) -> Result<Vec<Self>, JujuError> {
+        Ok(from_slice(json)?)
+    }
+
+    /// Parses the table that `charmcraft revisions` prints without `--format json`
+    ///
+    /// The first column is the revision, and the table has a header row. Only revisions are read.
+    pub fn parse_table(text: &str) -> Result<Vec<Self>, JujuError> {
+        text.lines()
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().next())
+            .map(|revision| {
+                Ok(Self {
+                    revision: revision.parse().map_err(|_| {
+                        JujuError::SubcommandError(
+                            "charmcraft revisions".into(),
+                            format!("unexpected revision `{}`", revision),
+                        )
+                    })?,
+                    version: None,
+                    status: None,
+                })
+            })
+            .collect()
+    }
+}
+
+/// What is released to a single channel
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct ChannelStatus {
+    pub status: ReleaseStatus,
+
+    /// The full channel name, e.g. `latest/stable`
+    pub channel: String,
+
+    #[serde(default)]
+    pub version: Option<String>,
+
+    #[serde(default)]
+    pub revision: Option<u32>,
+
+    #[serde(default)]
+    pub resources: Option<Vec<ReleasedResource>>,
+
+    #[serde(default)]
+    pub expires_at: Option<String>,
+}
+
+/// Base that a set of releases applies to
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct StatusBase {
+    pub name: String,
+    pub channel: String,
+    pub architecture: String,
+}
+
+/// Releases for a particular base
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct StatusMapping {
+    /// Charms that were uploaded without base information have no base
+    #[serde(default)]
+    pub base: Option<StatusBase>,
+
+    pub releases: Vec<ChannelStatus>,
+}
+
+/// Releases within a single track, e.g. `latest`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct TrackStatus {
+    pub track: String,
+    pub mappings: Vec<StatusMapping>,
+}
+
+/// Output of `charmcraft status <name> --format json`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Status {
+    pub tracks: Vec<TrackStatus>,
+}
+
+impl ChannelStatus {
+    /// Serializes the channel's status as JSON
+    pub fn to_json(&self) -> Result<String, JujuError> {
+        Ok(to_string_pretty(self)?)
+    }
+}
+
+impl Status {
+    /// Parses the JSON output of `charmcraft status`
+    pub fn parse(json: &[u8]) -> Result<Self, JujuError> {
+        Ok(from_slice(json)?)
+    }
+
+    /// Serializes the status as JSON, in the same form that `parse` reads
+    pub fn to_json(&self) -> Result<String, JujuError> {
+        Ok(to_string_pretty(self)?)
+    }
+
+    /// Returns the release in `channel` of the `latest` track for the first base that has one
+    ///
+    /// Tracking channels are resolved to the more stable channel that they follow.
+    pub fn release(&self, channel: &Channel) -> Option<&ChannelStatus> {
+        let name = format!("latest/{}", channel);
+        let release = self
+            .tracks
+            .iter()
+            .filter(|t| t.track == "latest")
+            .flat_map(|t| &t.mappings)
+            .flat_map(|m| &m.releases)
+            .find(|r| r.channel == name)?;
+
+        match (release.status, channel.more_stable()) {
+            (ReleaseStatus::Tracking, Some(next)) => self.release(&next),
+            _ => Some(release),
+        }
+    }
+
+    /// The highest revision released to any channel, on any track or base
+    pub fn max_revision(&self) -> Option<u32> {
+        self.tracks
+            .iter()
+            .flat_map(|t| &t.mappings)
+            .flat_map(|m| &m.releases)
+            .filter_map(|r| r.revision)
+            .max()
+    }
+
+    /// Whether `revision` is released to any channel, on any track or base
+    ///
+    /// The status only lists released revisions, so one that was uploaded but never released
+    /// isn't found.
+    pub fn has_revision(&self, revision: u32) -> bool {
+        self.tracks
+            .iter()
+            .flat_map(|t| &t.mappings)
+            .flat_map(|m| &m.releases)
+            .any(|r| r.revision == Some(revision))
+    }
+}
+
+/// Recently fetched statuses, keyed by charm name
+///
+/// Entries expire after `ttl`. Like `cmd::Runner`, caches never take part in equality
+/// comparisons, and cloning one takes a snapshot of its entries.
+pub(crate) struct StatusCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, Status)>>,
+}
+
+impl StatusCache {
+    /// The cached status of `name`, unless it's missing or has expired
+    pub(crate) fn get(&self, name: &str) -> Option<Status> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(name)
+            .filter(|(fetched, _)| fetched.elapsed() < self.ttl)
+            .map(|(_, status)| status.clone())
+    }
+
+    pub(crate) fn insert(&self, name: &str, status: Status) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(name.into(), (Instant::now(), status));
+    }
+
+    pub(crate) fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl Default for StatusCache {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(30),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Clone for StatusCache {
+    fn clone(&self) -> Self {
+        Self {
+            ttl: self.ttl,
+            entries: Mutex::new(self.entries.lock().unwrap().clone()),
+        }
+    }
+}
+
+impl fmt::Debug for StatusCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("StatusCache")
+    }
+}
+
+impl PartialEq for StatusCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Status of a charm with two resources released to stable, followed by candidate
+    pub(crate) const STATUS: &str = r#"[
+  {
+    "track": "latest",
+    "mappings": [
+      {
+        "base": {"name": "ubuntu", "channel": "20.04", "architecture": "amd64"},
+        "releases": [
+          {
+            "status": "open",
+            "channel": "latest/stable",
+            "version": "3",
+            "revision": 3,
+            "resources": [
+              {"name": "foo-image", "revision": 5},
+              {"name": "bar-image", "revision": 2}
+            ],
+            "expires_at": null
+          },
+          {
+            "status": "tracking",
+            "channel": "latest/candidate",
+            "version": null,
+            "revision": null,
+            "resources": null,
+            "expires_at": null
+          },
+          {
+            "status": "open",
+            "channel": "latest/beta",
+            "version": "4",
+            "revision": 4,
+            "resources": [
+              {"name": "foo-image", "revision": 6},
+              {"name": "bar-image", "revision": 2}
+            ],
+            "expires_at": null
+          },
+          {
+            "status": "closed",
+            "channel": "latest/edge",
+            "version": null,
+            "revision": null,
+            "resources": null,
+            "expires_at": null
+          }
+        ]
+      }
+    ]
+  }
+]"#;
+
+    #[test]
+    fn test_release() {
+        let status = Status::parse(STATUS.as_bytes()).unwrap();
+
+        assert_eq!(status.release(&Channel::Stable).unwrap().revision, Some(3));
+        assert_eq!(
+            status.release(&Channel::Candidate).unwrap().revision,
+            Some(3)
+        );
+        assert_eq!(status.release(&Channel::Beta).unwrap().revision, Some(4));
+        assert_eq!(
+            status.release(&Channel::Edge).unwrap().status,
+            ReleaseStatus::Closed
+        );
+
+        assert!(status.has_revision(4));
+        assert!(!status.has_revision(5));
+        assert_eq!(status.max_revision(), Some(4));
+        assert_eq!(Status::parse(b"[]").unwrap().max_revision(), None);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let status = Status::parse(STATUS.as_bytes()).unwrap();
+
+        assert_eq!(
+            Status::parse(status.to_json().unwrap().as_bytes()).unwrap(),
+            status
+        );
+    }
+
+    #[test]
+    fn test_latest_resource_revision() {
+        let json = br#"[
+  {"revision": 3, "created_at": "2021-10-01T00:00:00Z", "size": 1024, "digest": "sha256:c"},
+  {"revision": 5, "created_at": "2021-10-03T00:00:00Z", "size": 1024, "digest": "sha256:e"},
+  {"revision": 4, "created_at": "2021-10-02T00:00:00Z", "size": 1024, "digest": "sha256:d"}
+]"#;
+        let revisions = ResourceRevision::parse_all(json).unwrap();
+
+        assert_eq!(ResourceRevision::latest(&revisions), Some(5));
+        assert_eq!(revisions[0].size, Some(Size(1024)));
+        assert_eq!(ResourceRevision::latest(&[]), None);
+    }
+
+    #[test]
+    fn test_charm_revisions() {
+        let json = br#"[
+  {"revision": 2, "version": "2", "created_at": "2021-10-02T00:00:00Z", "status": "approved", "bases": []},
+  {"revision": 1, "version": "1", "created_at": "2021-10-01T00:00:00Z", "status": "approved", "bases": []}
+]"#;
+        let revisions = CharmRevision::parse_all(json).unwrap();
+        assert_eq!(revisions[0].revision, 2);
+        assert_eq!(revisions[0].status.as_deref(), Some("approved"));
+
+        let table = "Revision    Version    Created at    Status\n\
+                     2           2          2021-10-02    approved\n\
+                     1           1          2021-10-01    approved\n";
+        let revisions = CharmRevision::parse_table(table).unwrap();
+        assert_eq!(
+            revisions.iter().map(|r| r.revision).collect::<Vec<_>>(),
+            [2, 1]
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! Digest algorithms for content hashes
+
+use std::fmt;
+
+use sha2::digest::DynDigest;
+use sha2::{Sha256, Sha512};
+
+/// Algorithm to compute a content hash with
+///
+/// Hashes are written prefixed with the algorithm's name, e.g. `sha256:...`, as registries and
+/// provenance tooling expect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Digest {
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+impl Digest {
+    /// The algorithm's name, as used in prefixes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Digest::Sha256 => "sha256",
+            Digest::Sha512 => "sha512",
+        }
+    }
+
+    pub(crate) fn hasher(&self) -> Box<dyn DynDigest> {
+        match self {
+            Digest::Sha256 => Box::new(Sha256::default()),
+            Digest::Sha512 => Box::new(Sha512::default()),
+        }
+    }
+
+    /// Finishes `hasher`, formatting the hash as e.g. `sha256:<hex>`
+    pub(crate) fn finish(&self, hasher: Box<dyn DynDigest>) -> String {
+        let hex: String = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        format!("{}:{}", self.name(), hex)
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finish() {
+        let mut hasher = Digest::Sha256.hasher();
+        hasher.update(b"abc");
+
+        assert_eq!(
+            Digest::Sha256.finish(hasher),
+            "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -3,6 +3,8 @@
 use std::io::Error as IOError;
 
 use ex::io::Error as ExIOError;
+use semver::Error as SemverError;
+use serde_json::Error as JsonError;
 use serde_yaml::Error as YamlError;
 use thiserror::Error as ThisError;
 use zip::result::ZipError;
@@ -18,6 +20,9 @@ pub enum JujuError {
     #[error("YAML Error: {0}")]
     YamlError(#[from] YamlError),
 
+    #[error("JSON Error: {0}")]
+    JsonError(#[from] JsonError),
+
     #[error("Controller `{0}` not found")]
     ControllerNotFound(String),
 
@@ -33,12 +38,131 @@ pub enum JujuError {
     #[error("Resource {0} not found for {1}")]
     ResourceNotFound(String, String),
 
-    #[error("Error running subcommand `{0}`: `{1}`")]
+    #[error("Error running subcommand {0}: {1}")]
     SubcommandError(String, String),
 
-    #[error("Error while talking to charm store: {0}")]
+    #[error("Zip error: {0}")]
     ZipError(#[from] ZipError),
 
-    #[error("Error charm URL prefix: {0}")]
+    #[error("Application `{0}` has neither a charm nor a source")]
     MissingSourceError(String),
+
+    #[error("{0} isn't a buildable charm: {1}")]
+    NotBuildable(String, String),
+
+    #[error("{0} uses the operator framework, but has no executable dispatch script")]
+    MissingDispatch(String),
+
+    #[error("No entry {0} found in archive {1}")]
+    ArchiveEntryNotFound(String, String),
+
+    #[error("Invalid container `{0}`: {1}")]
+    InvalidContainer(String, String),
+
+    #[error("Charm {0} isn't local, and downloading it requires the `download` feature")]
+    RemoteCharm(String),
+
+    #[error("Image `{1}` for resource {0} can't be resolved: {2}")]
+    UnresolvableImage(String, String, String),
+
+    #[error("Relation `{1}` required by {0} is container-scoped, but {0} isn't a subordinate")]
+    InvalidRelationScope(String, String),
+
+    #[error("Invalid charm URL `{1}` at position {0}: {2}")]
+    InvalidCharmURL(usize, String, String),
+
+    #[error("Charm `{0}` has no Charmhub equivalent: {1}")]
+    NoCharmhubEquivalent(String, String),
+
+    #[error("No built .charm file found for {0}")]
+    ArtifactNotFound(String),
+
+    #[error("Nothing of {0} is released to {1}")]
+    NoRelease(String, String),
+
+    #[error("Malformed schema reference `{1}` for interface {0}")]
+    InvalidSchema(String, String),
+
+    #[error("Interface `{0}` should be named `{1}`")]
+    NonCanonicalInterface(String, String),
+
+    #[error("Unknown field `{1}` in {0}")]
+    UnknownField(String, String),
+
+    #[error("Promoting {0} would replace revision {2} in {1} with older revision {3}")]
+    WouldDowngrade(String, String, u32, u32),
+
+    #[error("{0} has no action `{1}`")]
+    UnknownAction(String, String),
+
+    #[error("Invalid parameters for action `{0}`: {1}")]
+    InvalidActionParams(String, String),
+
+    #[error("Renaming resource `{0}` to `{1}` would replace an existing resource")]
+    ResourceNameConflict(String, String),
+
+    #[error("Can't edit {0} in place: {1}")]
+    CannotEdit(String, String),
+
+    #[error("Unknown config option `{0}`")]
+    UnknownConfigOption(String),
+
+    #[error("Invalid value for config option `{0}`, expected {1}")]
+    InvalidConfigValue(String, String),
+
+    #[error("Invalid default for config option `{0}`: {1}")]
+    InvalidDefault(String, String),
+
+    #[error("Config option `{0}` in {1} changes the type of an earlier definition")]
+    ConfigConflict(String, String),
+
+    #[error("Variable `{0}` is referenced but not defined")]
+    UndefinedVariable(String),
+
+    #[error("Timed out waiting for {0}")]
+    Timeout(String),
+
+    #[error("Invalid version: {0}")]
+    SemverError(#[from] SemverError),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::io::ErrorKind;
+
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            JujuError::ResourceNotFound("foo-image".into(), "foo".into()).to_string(),
+            "Resource foo-image not found for foo"
+        );
+        assert_eq!(
+            JujuError::MissingSourceError("foo".into()).to_string(),
+            "Application `foo` has neither a charm nor a source"
+        );
+        assert_eq!(
+            JujuError::ZipError(ZipError::FileNotFound).to_string(),
+            "Zip error: specified file not found in archive"
+        );
+    }
+
+    #[test]
+    fn test_source() {
+        let wrapped = [
+            JujuError::from(IOError::new(ErrorKind::NotFound, "gone")),
+            JujuError::from(serde_yaml::from_str::<u32>("foo").unwrap_err()),
+            JujuError::from(serde_json::from_str::<u32>("foo").unwrap_err()),
+            JujuError::from(ZipError::FileNotFound),
+            JujuError::from(semver::Version::parse("foo").unwrap_err()),
+        ];
+
+        for err in &wrapped {
+            assert!(err.source().is_some(), "{:?}", err);
+        }
+
+        assert!(JujuError::NoActiveController.source().is_none());
+    }
 }
@@ -2,9 +2,15 @@ pub mod bundle;
 pub mod channel;
 pub mod charm_source;
 pub mod charm_url;
+pub mod charmhub;
 pub mod cmd;
+pub mod digest;
 pub mod error;
 pub mod local;
 pub mod paths;
 pub mod series;
+pub mod size;
 pub mod store;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+pub mod toolchain;
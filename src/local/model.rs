@@ -58,8 +58,8 @@ impl ModelYaml {
                     Ok(name.to_string())
                 } else {
                     Err(JujuError::ModelNotFound(
-                        controller.to_string(),
                         name.to_string(),
+                        controller.to_string(),
                     ))
                 }
             }
@@ -78,3 +78,50 @@ impl ModelYaml {
         }
     }
 }
+
+/// Builds the `-m` flag that points a `juju` command at a particular model
+///
+/// If only the controller is given, its current model is looked up in `models.yaml`. With
+/// neither, no flag is emitted and Juju uses the current context.
+pub fn model_args(controller: Option<&str>, model: Option<&str>) -> Result<Vec<String>, JujuError> {
+    let target = match (controller, model) {
+        (Some(c), Some(m)) => format!("{}:{}", c, m),
+        (Some(c), None) => format!("{}:{}", c, ModelYaml::load()?.validate_name(c, None)?),
+        (None, Some(m)) => m.to_string(),
+        (None, None) => return Ok(vec![]),
+    };
+
+    Ok(vec!["-m".into(), target])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_args() {
+        assert_eq!(
+            model_args(Some("uk8s"), Some("kubeflow")).unwrap(),
+            ["-m", "uk8s:kubeflow"]
+        );
+        assert_eq!(
+            model_args(None, Some("kubeflow")).unwrap(),
+            ["-m", "kubeflow"]
+        );
+        assert!(model_args(None, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_validate_name_not_found() {
+        let models: ModelYaml =
+            serde_yaml::from_str("controllers:\n  uk8s:\n    models: {}\n").unwrap();
+
+        assert_eq!(
+            models
+                .validate_name("uk8s", Some("kubeflow"))
+                .unwrap_err()
+                .to_string(),
+            "Model `kubeflow` not found in controller uk8s"
+        );
+    }
+}
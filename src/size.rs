@@ -0,0 +1,188 @@
+//! Sizes written with Juju's suffixes, such as `10G`
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{Error, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Multipliers that Juju accepts, from smallest to largest
+///
+/// Sizes are 1024-based, regardless of whether they're written as e.g. `G`, `GB`, or `GiB`.
+const SUFFIXES: &[(char, u64)] = &[
+    ('K', 1 << 10),
+    ('M', 1 << 20),
+    ('G', 1 << 30),
+    ('T', 1 << 40),
+    ('P', 1 << 50),
+    ('E', 1 << 60),
+];
+
+/// A size in bytes, such as the `minimum-size` of storage
+///
+/// Parsed from and displayed as e.g. `512M`. With no suffix, megabytes are implied, while a bare
+/// `B` means bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Size(pub u64);
+
+impl Size {
+    /// The size in bytes
+    pub fn bytes(&self) -> u64 {
+        self.0
+    }
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(s.len());
+        let (number, suffix) = s.split_at(split);
+
+        let number: u64 = number
+            .parse()
+            .map_err(|_| format!("Size `{}` doesn't start with a number", s))?;
+
+        if suffix == "B" {
+            return Ok(Size(number));
+        }
+
+        let unit = suffix
+            .strip_suffix("iB")
+            .or_else(|| suffix.strip_suffix('B'))
+            .unwrap_or(suffix);
+        let multiplier = match unit.chars().collect::<Vec<_>>()[..] {
+            [] => 1 << 20,
+            [unit] => SUFFIXES
+                .iter()
+                .find(|(suffix, _)| *suffix == unit.to_ascii_uppercase())
+                .map(|(_, multiplier)| *multiplier)
+                .ok_or_else(|| format!("Unknown size suffix in `{}`", s))?,
+            _ => return Err(format!("Unknown size suffix in `{}`", s)),
+        };
+
+        number
+            .checked_mul(multiplier)
+            .map(Size)
+            .ok_or_else(|| format!("Size `{}` is too large", s))
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let largest = SUFFIXES
+            .iter()
+            .rev()
+            .find(|(_, multiplier)| self.0 != 0 && self.0.is_multiple_of(*multiplier));
+
+        match largest {
+            Some((suffix, multiplier)) => write!(f, "{}{}", self.0 / multiplier, suffix),
+            None => write!(f, "{}B", self.0),
+        }
+    }
+}
+
+impl Serialize for Size {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Size {
+    fn deserialize<D>(deserializer: D) -> Result<Size, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(SizeVisitor)
+    }
+}
+
+/// Accepts sizes written as strings, or as plain numbers of megabytes
+struct SizeVisitor;
+
+impl Visitor<'_> for SizeVisitor {
+    type Value = Size;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a size such as `10G`")
+    }
+
+    fn visit_str<E: Error>(self, s: &str) -> Result<Size, E> {
+        s.parse().map_err(E::custom)
+    }
+
+    fn visit_u64<E: Error>(self, n: u64) -> Result<Size, E> {
+        self.visit_str(&n.to_string())
+    }
+}
+
+/// (De)serializes an optional size as a plain number of bytes, as Charmhub reports them
+pub(crate) mod bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Size;
+
+    pub fn serialize<S>(size: &Option<Size>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match size {
+            Some(size) => serializer.serialize_u64(size.bytes()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Size>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Option::<u64>::deserialize(deserializer)?.map(Size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("10G".parse::<Size>().unwrap().bytes(), 10 << 30);
+        assert_eq!("512M".parse::<Size>().unwrap().bytes(), 512 << 20);
+        assert_eq!("2GiB".parse::<Size>(), "2G".parse());
+        assert_eq!("100".parse::<Size>(), "100M".parse());
+        assert!("10Q".parse::<Size>().is_err());
+        assert!("G".parse::<Size>().is_err());
+        assert_eq!("100B".parse::<Size>().unwrap().bytes(), 100);
+        assert_eq!("100MB".parse::<Size>(), "100M".parse());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Size(10 << 30).to_string(), "10G");
+        assert_eq!(Size(512 << 20).to_string(), "512M");
+        assert_eq!(Size(1536 << 20).to_string(), "1536M");
+        assert_eq!(Size(100).to_string(), "100B");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for size in &[
+            Size(0),
+            Size(100),
+            Size(1536),
+            Size(512 << 20),
+            Size(10 << 30),
+        ] {
+            assert_eq!(size.to_string().parse::<Size>().unwrap(), *size);
+        }
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let sizes: Vec<Size> = serde_yaml::from_str("[10G, 512]").unwrap();
+        assert_eq!(sizes, [Size(10 << 30), Size(512 << 20)]);
+    }
+}
@@ -51,6 +51,10 @@ use std::collections::HashMap;
 #[serde(rename_all = "kebab-case")]
 pub enum ResourceType {
     OciImage,
+
+    /// A resource type that this library doesn't know about yet
+    #[serde(other)]
+    Unknown,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -66,6 +70,13 @@ pub struct Resource {
     pub size: u32,
 }
 
+impl Resource {
+    /// Whether this resource has one of the types that this library understands
+    pub fn is_known_type(&self) -> bool {
+        self.kind != ResourceType::Unknown
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "PascalCase")]
 pub struct ShowMetadata {
@@ -167,3 +178,29 @@ pub struct Show {
 //    let parsed: MetaResponse = from_str(string).unwrap();
 //    println!("DEBUG: {:?}", parsed);
 //}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::from_str;
+
+    use super::*;
+
+    #[test]
+    fn test_unknown_resource_type() {
+        let resource: Resource = from_str(
+            r#"
+name: blob
+type: quantum-blob
+path: blob.bin
+description: Something from the future
+revision: 1
+fingerprint: null
+size: 42
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(resource.kind, ResourceType::Unknown);
+        assert!(!resource.is_known_type());
+    }
+}
@@ -0,0 +1,81 @@
+//! Utilities for testing code that works with charms
+//!
+//! Only available within this crate's tests, or with the `test-util` feature enabled.
+
+use std::io::{Cursor, Write};
+
+use serde_yaml::to_vec;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::charm_source::{Base, BaseSpec, Charmcraft, Config, Metadata};
+
+/// Builds an in-memory `.charm` file that `CharmSource::load` can read
+///
+/// The archive contains `metadata.yaml`, `config.yaml` if `config` is given, and a minimal
+/// `charmcraft.yaml` that builds on and runs on Ubuntu 20.04.
+pub fn make_charm_zip(metadata: &Metadata, config: Option<&Config>) -> Vec<u8> {
+    let base = Base {
+        name: "ubuntu".into(),
+        channel: "20.04".into(),
+    };
+    let charmcraft = Charmcraft {
+        bases: vec![BaseSpec {
+            build_on: vec![base.clone()],
+            run_on: vec![base],
+        }],
+        architectures: vec![],
+    };
+
+    let mut files = vec![
+        ("metadata.yaml", to_vec(metadata).unwrap()),
+        ("charmcraft.yaml", to_vec(&charmcraft).unwrap()),
+    ];
+
+    if let Some(config) = config {
+        files.push(("config.yaml", to_vec(config).unwrap()));
+    }
+
+    zip_with(&files)
+}
+
+/// Builds an in-memory zip archive holding `files`, given as name and contents
+pub fn zip_with<C: AsRef<[u8]>>(files: &[(&str, C)]) -> Vec<u8> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+    for (name, contents) in files {
+        zip.start_file(*name, FileOptions::default()).unwrap();
+        zip.write_all(contents.as_ref()).unwrap();
+    }
+
+    zip.finish().unwrap().into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use ex::fs::write;
+    use serde_yaml::from_str;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::charm_source::CharmSource;
+
+    #[test]
+    fn test_make_charm_zip() {
+        let metadata: Metadata =
+            from_str("name: foo\nsummary: A foo charm\ndescription: Does foo things\n").unwrap();
+        let config: Config = from_str(
+            "options:\n  bar:\n    type: string\n    description: Bar\n    default: baz\n",
+        )
+        .unwrap();
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("foo.charm");
+        write(&path, make_charm_zip(&metadata, Some(&config))).unwrap();
+
+        let charm = CharmSource::load(&path).unwrap();
+        assert_eq!(charm.metadata, metadata);
+        assert_eq!(charm.config, Some(config));
+        assert_eq!(charm.charmcraft.bases[0].run_on[0].channel, "20.04");
+    }
+}
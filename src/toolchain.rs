@@ -0,0 +1,114 @@
+//! The external tools that juju-rs shells out to
+
+use std::sync::{Arc, OnceLock};
+
+use semver::Version;
+
+use crate::cmd::{CommandRunner, Runner};
+use crate::error::JujuError;
+
+/// Handle to the installed `charmcraft`, `juju`, etc.
+///
+/// Tool versions are queried once and then cached.
+#[derive(Debug, Default)]
+pub struct Toolchain {
+    runner: Runner,
+    charmcraft_version: OnceLock<Version>,
+}
+
+impl Toolchain {
+    /// Uses the tools installed on the host system
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the tools through `runner` instead of as subprocesses
+    pub fn with_runner(runner: Arc<dyn CommandRunner>) -> Self {
+        Self {
+            runner: Runner::new(runner),
+            ..Default::default()
+        }
+    }
+
+    /// The version of `charmcraft`, as reported by `charmcraft version`
+    pub fn charmcraft_version(&self) -> Result<Version, JujuError> {
+        if let Some(version) = self.charmcraft_version.get() {
+            return Ok(version.clone());
+        }
+
+        let output = self.runner.get_output("charmcraft", &["version".into()])?;
+        let version = parse_version(&String::from_utf8_lossy(&output))?;
+
+        Ok(self.charmcraft_version.get_or_init(|| version).clone())
+    }
+}
+
+/// Whether charmcraft `version` accepts `--format json` on subcommands such as
+/// `resource-revisions`, which older releases only print as tables
+pub(crate) fn supports_json_format(version: &Version) -> bool {
+    *version >= Version::new(2, 2, 0)
+}
+
+/// Parses a version from output such as `charmcraft 2.5.0` or `Version: 1.2.1.post12+git`
+///
+/// Anything after the `major.minor.patch` triple that isn't valid semver is dropped.
+fn parse_version(output: &str) -> Result<Version, JujuError> {
+    let word = output
+        .split_whitespace()
+        .last()
+        .unwrap_or_default()
+        .trim_start_matches('v');
+
+    if let Ok(version) = Version::parse(word) {
+        return Ok(version);
+    }
+
+    let triple: Vec<_> = word.splitn(4, '.').take(3).collect();
+    let patch: String = triple
+        .get(2)
+        .map(|p| p.chars().take_while(char::is_ascii_digit).collect())
+        .unwrap_or_default();
+    let core = format!(
+        "{}.{}.{}",
+        triple.first().copied().unwrap_or_default(),
+        triple.get(1).copied().unwrap_or_default(),
+        patch
+    );
+
+    Ok(Version::parse(&core)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::mock::MockRunner;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            parse_version("charmcraft 2.5.0\n").unwrap(),
+            Version::new(2, 5, 0)
+        );
+        assert_eq!(
+            parse_version("Version: 1.2.1.post12+git3f8a2b1\n").unwrap(),
+            Version::new(1, 2, 1)
+        );
+        assert!(parse_version("unknown").is_err());
+    }
+
+    #[test]
+    fn test_charmcraft_version_cached() {
+        let mock = MockRunner::new(|_, _| Ok(b"charmcraft 2.5.0\n".to_vec()));
+        let toolchain = Toolchain::with_runner(mock.clone());
+
+        assert_eq!(
+            toolchain.charmcraft_version().unwrap(),
+            Version::new(2, 5, 0)
+        );
+        assert_eq!(
+            toolchain.charmcraft_version().unwrap(),
+            Version::new(2, 5, 0)
+        );
+        assert_eq!(mock.calls(), [["charmcraft", "version"]]);
+    }
+}
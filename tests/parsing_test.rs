@@ -5,7 +5,7 @@ use std::path::PathBuf;
 
 use serde_yaml::from_slice;
 
-use juju::bundle::{Annotations, Application, Bundle};
+use juju::bundle::{Annotations, Application, Bundle, Value};
 use juju::local::controller::{Controller, KubernetesPortForwardConfig, ProxyConfig};
 use juju::local::ControllerYaml;
 use juju::series::Series;
@@ -49,11 +49,57 @@ fn parse_bundle() {
                 .map(String::from)
                 .collect::<Vec<_>>()],
             bundle: Some(Series::Kubernetes),
+            machines: HashMap::new(),
             series: None,
         }
     );
 }
 
+#[test]
+fn parse_exported_bundle() {
+    let yaml = fs::read_to_string("tests/examples/bundle-exported.yaml").unwrap();
+    let bundle = Bundle::from_exported(&yaml).unwrap();
+
+    assert_eq!(bundle.series, Some(Series::Focal));
+
+    let postgresql = &bundle.applications["postgresql"];
+    assert_eq!(postgresql.scale, 2);
+    assert_eq!(postgresql.to, ["0", "lxd:1"]);
+    assert_eq!(
+        postgresql.options["plugin_audit_enable"],
+        Value::Boolean(true)
+    );
+    assert_eq!(
+        postgresql.options["shared_buffers_fraction"],
+        Value::Float(0.25)
+    );
+    assert_eq!(postgresql.endpoint_bindings[""], "alpha");
+
+    let ubuntu = &bundle.applications["ubuntu"];
+    assert_eq!(ubuntu.resources["snapd"], "1");
+
+    let mut machine_ids: Vec<_> = bundle.machines.keys().collect();
+    machine_ids.sort();
+    assert_eq!(machine_ids, ["0", "1"]);
+    assert_eq!(
+        bundle.machines["0"].constraints.as_deref(),
+        Some("arch=amd64")
+    );
+
+    assert_eq!(
+        bundle.relations,
+        [vec!["postgresql:juju-info", "ubuntu:juju-info"]]
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bundle.yaml");
+    bundle.save(&path).unwrap();
+    assert!(fs::read_to_string(&path)
+        .unwrap()
+        .contains("shared_buffers_fraction: 0.25"));
+    assert_eq!(Bundle::load(&path).unwrap(), bundle);
+}
+
 #[test]
 fn parse_controller_yaml() {
     let bytes = fs::read(PathBuf::from("tests/examples/controllers.yaml")).unwrap();
@@ -138,6 +184,7 @@ fn parse_metadata_yaml() {
                     "OCI image for the Super App (hub.docker.com/_/super-app)".into(),
                 ),
                 upstream_source: None,
+                build: None,
             },
         ),
         (
@@ -211,6 +258,7 @@ fn parse_metadata_yaml() {
         devices: HashMap::new(),
         extra_bindings: HashMap::new(),
         series: None,
+        charm_user: None,
     };
 
     assert_eq!(parsed, expected);